@@ -1,12 +1,12 @@
 #version 450 core
 
-#define RADIUS 30.0
-#define BACK_DISTANCE 120.0
-#define FRONT_DISTANCE 330.0
-
 #include "cam.glsl"
 #include "light.glsl"
 
+#define RADIUS cam.bg_radius
+#define BACK_DISTANCE cam.bg_back_distance
+#define FRONT_DISTANCE cam.bg_front_distance
+
 layout (location=0) out float z;
 
 vec3 luf() { return vec3(-RADIUS, -RADIUS, light.pos.z - BACK_DISTANCE); }
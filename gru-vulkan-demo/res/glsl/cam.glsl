@@ -1,4 +1,8 @@
 layout (set=0, binding=0) uniform Camera
 {
     mat4 proj;
+    //box around the loaded blocks, see Tunables::bg_*
+    float bg_radius;
+    float bg_back_distance;
+    float bg_front_distance;
 } cam;
\ No newline at end of file
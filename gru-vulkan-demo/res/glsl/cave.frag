@@ -4,13 +4,18 @@
 
 layout (location=0) in vec3 pos;
 layout (location=1) in vec3 normal;
-layout (location=2) in vec2 tex_coords;
-layout (location=3) in float brightness;
+layout (location=2) in float brightness;
+layout (location=3) in vec3 color;
 
-layout (set=2, binding=0) uniform sampler2D tex;
+//one layer per biome texture
+layout (set=2, binding=0) uniform sampler2DArray tex;
 
 layout (location=0) out vec4 frag_color; 
 
+//world units per repeat of the texture and how sharply the three projections hand over with the normal
+#define TEXTURE_SCALE 0.05
+#define TRIPLANAR_SHARPNESS 4.0
+
 vec3 light_factor()
 {
 	vec3 pxl_to_cam = pos - light.pos;
@@ -20,11 +25,33 @@ vec3 light_factor()
 
 	float lichtkegel = smoothstep(light.cos_angle_outer, light.cos_angle_inner, dot(pxl_to_cam_norm, light.dir));
 	float norm_stuff = clamp(dot(normal, -light.dir) / (1 + distance) / (1 + distance), 0, 1);
-	return (lichtkegel * light.color * norm_stuff + light.ambient) * brightness;
+	return (lichtkegel * light_color() * norm_stuff + light.ambient) * brightness;
+}
+
+//triplanar mapping: the texture projected along each axis, weighted by how much the surface faces it
+vec3 triplanar(float layer)
+{
+    vec3 weights = pow(abs(normal), vec3(TRIPLANAR_SHARPNESS));
+    weights /= weights.x + weights.y + weights.z;
+    vec3 coords = pos * TEXTURE_SCALE;
+    return texture(tex, vec3(coords.yz, layer)).rgb * weights.x
+         + texture(tex, vec3(coords.xz, layer)).rgb * weights.y
+         + texture(tex, vec3(coords.xy, layer)).rgb * weights.z;
+}
+
+//the layers of the biome at z and the one it turns into, and how far it has, like biome::Blend::at
+//worked out per pixel, interpolated layers would pick wrong ones in triangles across a biome border
+vec3 texture_blend(float z)
+{
+    float index = floor(max(z, 0.0) / light.biome_length);
+    float t = clamp((z - (index + 1.0) * light.biome_length) / light.biome_blend + 1.0, 0.0, 1.0);
+    return vec3(mod(index, light.biome_count), mod(index + 1.0, light.biome_count), t * t * (3.0 - 2.0 * t));
 }
 
 void main()
 {
-    frag_color.rgb = texture(tex, tex_coords).rgb * light_factor() + light.flash_ambient;
+    vec3 blend = texture_blend(pos.z);
+    vec3 rock = mix(triplanar(blend.x), triplanar(blend.y), blend.z);
+    frag_color.rgb = rock * color * light_factor() + light.flash_ambient;
     frag_color.a = 1;
 }
\ No newline at end of file
@@ -18,6 +18,6 @@ void main()
 	float lichtkegel = smoothstep(light.cos_angle_outer, light.cos_angle_inner, dot(pxl_to_cam_norm, light.dir)) * 5;
 	float norm_stuff = clamp(1 / (1 + distance) / (1 + distance), 0, 1);
 	
-	frag_color.rgb = color * (lichtkegel * light.color * norm_stuff + light.ambient) * brightness + light.flash_ambient;
+	frag_color.rgb = color * (lichtkegel * light_color() * norm_stuff + light.ambient) * brightness + light.flash_ambient;
     frag_color.a = 1;
 }
\ No newline at end of file
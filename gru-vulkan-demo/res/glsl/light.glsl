@@ -3,9 +3,20 @@ layout (std140, set=1, binding=0) uniform Light
     float z_bias;
     float cos_angle_inner;
     float cos_angle_outer;
+    float battery;
     vec3 ambient;
     vec3 flash_ambient;
     vec3 color;
     vec3 pos;
     vec3 dir;
-} light;
\ No newline at end of file
+    //see biome::Blend::at
+    float biome_length;
+    float biome_blend;
+    float biome_count;
+} light;
+
+//an emptying battery gives a warmer light
+vec3 light_color()
+{
+    return light.color * mix(vec3(1.0, 0.6, 0.4), vec3(1.0), smoothstep(0.0, 0.25, light.battery));
+}
\ No newline at end of file
@@ -3,6 +3,7 @@
 #include "text.glsl"
 
 layout (location=0) in vec3 coords;
+layout (location=1) in vec3 col;
 
 layout (location=0) out vec4 frag_color;
 
@@ -20,7 +21,7 @@ float samp(vec3 uv, float w)
 
 void main()
 {
-    if(coords.p < -0.5) frag_color = vec4(0.2, 0.2, 0.6, 1.0);
+    if(coords.p < -0.5) frag_color = vec4(col, 1.0);
     else
     {
         vec3 uv = coords;
@@ -36,7 +37,7 @@ void main()
           + samp(vec3(box.xw, uv.p), width)
           + samp(vec3(box.zy, uv.p), width);
         alpha = (alpha + 0.5 * asum) / 3.0;
-        frag_color = vec4(0.0, 0.1, 0.8, alpha);
+        frag_color = vec4(col, alpha);
 
         //float sd = texture(tex, coords).r;
         //float alpha = smoothstep(0.5 - SIG, 0.5 + SIG, sd);
@@ -0,0 +1,262 @@
+use std::io::Write;
+use ahash::AHashSet as HashSet;
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action
+{
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Jump,
+    Grapple,
+    Reel,
+    ToggleLight,
+    NoclipOn,
+    NoclipOff,
+    Screenshot,
+    ScreenshotLarge,
+    Fullscreen,
+    Pause,
+    Confirm,
+    NewSeed,
+    Quit
+}
+
+impl Action
+{
+    pub const ALL: [Self; 17] =
+    [
+        Self::Forward, Self::Backward, Self::Left, Self::Right, Self::Jump, Self::Grapple, Self::Reel, Self::ToggleLight, Self::NoclipOn, Self::NoclipOff,
+        Self::Screenshot, Self::ScreenshotLarge, Self::Fullscreen, Self::Pause, Self::Confirm, Self::NewSeed, Self::Quit
+    ];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Self::Forward => "forward",
+            Self::Backward => "backward",
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Jump => "jump",
+            Self::Grapple => "grapple",
+            Self::Reel => "reel",
+            Self::ToggleLight => "toggle_light",
+            Self::NoclipOn => "noclip_on",
+            Self::NoclipOff => "noclip_off",
+            Self::Screenshot => "screenshot",
+            Self::ScreenshotLarge => "screenshot_large",
+            Self::Fullscreen => "fullscreen",
+            Self::Pause => "pause",
+            Self::Confirm => "confirm",
+            Self::NewSeed => "new_seed",
+            Self::Quit => "quit"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> { Self::ALL.into_iter().find(|action| action.name() == name) }
+}
+
+//keys are physical positions named after the US layout, so WASD stays in place on any layout
+const KEYS: [KeyCode; 78] =
+[
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF, KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI,
+    KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR,
+    KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4, KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6, KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+    KeyCode::Space, KeyCode::Tab, KeyCode::Enter, KeyCode::Escape, KeyCode::Backspace, KeyCode::CapsLock,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::AltLeft, KeyCode::AltRight,
+    KeyCode::Minus, KeyCode::Equal, KeyCode::BracketLeft, KeyCode::BracketRight, KeyCode::Backslash, KeyCode::Semicolon, KeyCode::Quote,
+    KeyCode::Backquote, KeyCode::Comma, KeyCode::Period, KeyCode::Slash,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Pause
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button
+{
+    Key(KeyCode),
+    Mouse(MouseButton)
+}
+
+impl Button
+{
+    //winit's names for keys, Mouse<Left|Right|Middle|Back|Forward|number> for mouse buttons
+    pub fn name(self) -> String
+    {
+        match self
+        {
+            Self::Key(key) => format!("{key:?}"),
+            Self::Mouse(MouseButton::Other(number)) => format!("Mouse{number}"),
+            Self::Mouse(button) => format!("Mouse{button:?}")
+        }
+    }
+
+    //short upper case name for the menus
+    pub fn label(self) -> String { self.name().trim_start_matches("Key").trim_start_matches("Digit").to_uppercase() }
+
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        if let Some(button) = name.strip_prefix("Mouse")
+        {
+            return match button
+            {
+                "Left" => Some(MouseButton::Left),
+                "Right" => Some(MouseButton::Right),
+                "Middle" => Some(MouseButton::Middle),
+                "Back" => Some(MouseButton::Back),
+                "Forward" => Some(MouseButton::Forward),
+                _ => button.parse().ok().map(MouseButton::Other)
+            }.map(Self::Mouse);
+        }
+        KEYS.into_iter().map(Self::Key).find(|key| key.name() == name)
+    }
+}
+
+//which buttons trigger which action, every action can have any number of them
+pub struct Bindings
+{
+    buttons: Vec<(Action, Vec<Button>)>
+}
+
+impl Bindings
+{
+    pub fn new() -> Self
+    {
+        let key = Button::Key;
+        let buttons = Action::ALL.into_iter().map(|action| (action, match action
+        {
+            Action::Forward => vec![key(KeyCode::KeyW), key(KeyCode::ArrowUp)],
+            Action::Backward => vec![key(KeyCode::KeyS), key(KeyCode::ArrowDown)],
+            Action::Left => vec![key(KeyCode::KeyA), key(KeyCode::ArrowLeft)],
+            Action::Right => vec![key(KeyCode::KeyD), key(KeyCode::ArrowRight)],
+            Action::Jump => vec![key(KeyCode::Space)],
+            Action::Grapple => vec![Button::Mouse(MouseButton::Right), key(KeyCode::KeyE)],
+            Action::Reel => vec![Button::Mouse(MouseButton::Left), key(KeyCode::KeyR)],
+            Action::ToggleLight => vec![key(KeyCode::KeyF)],
+            Action::NoclipOn => vec![key(KeyCode::KeyK)],
+            Action::NoclipOff => vec![key(KeyCode::KeyL)],
+            Action::Screenshot => vec![key(KeyCode::KeyP)],
+            Action::ScreenshotLarge => vec![key(KeyCode::F12)],
+            Action::Fullscreen => vec![key(KeyCode::F11)],
+            Action::Pause => vec![key(KeyCode::Tab)],
+            Action::Confirm => vec![key(KeyCode::Enter)],
+            Action::NewSeed => vec![key(KeyCode::KeyN)],
+            Action::Quit => vec![key(KeyCode::Escape)]
+        })).collect();
+        Self { buttons }
+    }
+
+    //"action = button, button" per line, actions missing from the file or on broken lines keep their defaults
+    //a missing file is created with the defaults, so there is something to edit,
+    //one that can't be read is left alone and the defaults are used
+    pub fn load(path: &str) -> Self
+    {
+        let mut bindings = Self::new();
+        let text = match std::fs::read_to_string(path)
+        {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound =>
+            {
+                bindings.save(path);
+                return bindings;
+            },
+            Err(err) =>
+            {
+                eprintln!("{path}: {err}, using the default bindings");
+                return bindings;
+            }
+        };
+        for (number, line) in text.lines().enumerate()
+        {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() { continue; }
+            //a broken line, say from an older file or a key winit names differently, leaves its action at the default
+            match Self::parse_line(line)
+            {
+                Ok((action, buttons)) => bindings.buttons.iter_mut().find(|(bound, _)| *bound == action).unwrap().1 = buttons,
+                Err(err) => eprintln!("{path}:{}: {err}, skipping the line", number + 1)
+            }
+        }
+        bindings
+    }
+
+    fn parse_line(line: &str) -> Result<(Action, Vec<Button>), String>
+    {
+        let (action, buttons) = line.split_once('=').ok_or("expected \"action = button, button\"")?;
+        let action = Action::from_name(action.trim()).ok_or_else(|| format!("unknown action \"{}\"", action.trim()))?;
+        let buttons = buttons.split(',').map(str::trim).filter(|name| !name.is_empty())
+            .map(|name| Button::from_name(name).ok_or_else(|| format!("unknown button \"{name}\""))).collect::<Result<_, _>>()?;
+        Ok((action, buttons))
+    }
+
+    pub fn save(&self, path: &str)
+    {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path).unwrap_or_else(|err| panic!("{path}: {err}")));
+        writeln!(file, "# action = button, button (key names as in winit's KeyCode, mouse buttons as MouseLeft, MouseRight, ...)").unwrap();
+        for (action, buttons) in &self.buttons
+        {
+            let names: Vec<_> = buttons.iter().map(|button| button.name()).collect();
+            writeln!(file, "{} = {}", action.name(), names.join(", ")).unwrap();
+        }
+    }
+
+    //a button bound to several actions triggers the first one
+    pub fn action(&self, button: Button) -> Option<Action>
+    {
+        self.buttons.iter().find(|(_, buttons)| buttons.contains(&button)).map(|(action, _)| *action)
+    }
+
+    pub fn buttons(&self, action: Action) -> &[Button]
+    {
+        &self.buttons.iter().find(|(bound, _)| *bound == action).unwrap().1
+    }
+}
+
+//tracks which buttons are down and turns their changes into action changes
+pub struct Controls
+{
+    bindings: Bindings,
+    held: HashSet<Button>
+}
+
+impl Controls
+{
+    pub fn new(bindings: Bindings) -> Self { Self { bindings, held: HashSet::new() } }
+
+    //the first button bound to an action, as shown in the menus
+    pub fn label(&self, action: Action) -> String { self.bindings.buttons(action).first().map_or_else(|| "UNBOUND".to_owned(), |button| button.label()) }
+
+    pub fn held(&self, action: Action) -> bool { self.bindings.buttons(action).iter().any(|button| self.held.contains(button)) }
+
+    //Some((action, pressed)) if the action changed, so key repeat and a second binding of a held action are ignored
+    pub fn button(&mut self, button: Button, pressed: bool) -> Option<(Action, bool)>
+    {
+        let action = self.bindings.action(button)?;
+        let was_held = self.held(action);
+        if pressed { self.held.insert(button); } else { self.held.remove(&button); }
+        let held = self.held(action);
+        (held != was_held).then_some((action, held))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn broken_lines_skipped()
+    {
+        let path = std::env::temp_dir().join("cave-jumper-bindings-test.cfg").to_str().unwrap().to_owned();
+        std::fs::write(&path, "jump = KeyJ, MouseLeft\nforward KeyI\nfly = KeyV\nleft = KeyH, NoSuchKey\n# right = KeyX\nquit =\n").unwrap();
+        let bindings = Bindings::load(&path);
+        let defaults = Bindings::new();
+        assert!(bindings.buttons(Action::Jump) == [Button::Key(KeyCode::KeyJ), Button::Mouse(MouseButton::Left)]);
+        for action in [Action::Forward, Action::Left, Action::Right] { assert!(bindings.buttons(action) == defaults.buttons(action)); }
+        assert!(bindings.buttons(Action::Quit).is_empty());
+    }
+}
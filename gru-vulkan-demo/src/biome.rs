@@ -0,0 +1,66 @@
+use gru_misc::math::*;
+use crate::consts;
+
+//a stretch of the cave with its own look, runs pass through them in order and start over after the last
+pub struct Biome
+{
+    pub name: &'static str,
+    //relative to the configured noise
+    pub frequency: f64,
+    pub persistence: f64,
+    //added to cave_gen_bias, higher closes the cave up
+    pub bias: f32,
+    //relative to cave_radius, at most 1 as blocks are only meshed out to twice cave_radius
+    pub radius: f32,
+    //the rock color goes from the first to the second with the noise
+    pub palette: [(f32, f32, f32); 2],
+    //loaded into the texture array layer of the same index as the biome
+    pub texture: &'static str,
+    pub ambient: (f32, f32, f32)
+}
+
+pub const BIOMES: [Biome; 4] =
+[
+    Biome { name: "sandstone", frequency: 1.0, persistence: 1.0, bias: 0.0, radius: 1.0, palette: [(0.8, 0.75, 0.65), (1.1, 1.0, 0.85)], texture: "data/rocks.png", ambient: consts::AMBIENT_LIGHT_COLOR },
+    Biome { name: "basalt", frequency: 0.7, persistence: 0.8, bias: 0.1, radius: 0.85, palette: [(0.35, 0.35, 0.4), (0.6, 0.6, 0.7)], texture: "data/basalt.png", ambient: (0.1, 0.11, 0.16) },
+    Biome { name: "crystal", frequency: 1.5, persistence: 1.1, bias: -0.1, radius: 1.0, palette: [(0.5, 0.7, 0.9), (0.9, 1.1, 1.3)], texture: "data/crystal.png", ambient: (0.12, 0.2, 0.26) },
+    Biome { name: "magma", frequency: 1.2, persistence: 0.9, bias: 0.05, radius: 0.75, palette: [(0.6, 0.25, 0.15), (1.3, 0.6, 0.3)], texture: "data/magma.png", ambient: (0.3, 0.12, 0.06) }
+];
+
+//where z lies between two neighbouring biomes
+#[derive(Clone, Copy)]
+pub struct Blend
+{
+    pub from: usize,
+    pub to: usize,
+    //0 is all from, 1 all to
+    pub weight: f32,
+    //derivative of weight by z
+    pub slope: f32
+}
+
+impl Blend
+{
+    //each biome is BIOME_LENGTH long and turns into the next over its last BIOME_BLEND units, the spawn side stays in the first
+    pub fn at(z: f32) -> Self
+    {
+        let index = (z.max(0.0) / consts::BIOME_LENGTH).floor();
+        let t = ((z - (index + 1.0) * consts::BIOME_LENGTH) / consts::BIOME_BLEND + 1.0).clamp(0.0, 1.0);
+        let (from, to) = (index as usize % BIOMES.len(), (index as usize + 1) % BIOMES.len());
+        //smoothstep, so parameters change without kinks
+        let (weight, slope) = (t * t * (3.0 - 2.0 * t), 6.0 * t * (1.0 - t) / consts::BIOME_BLEND);
+        Self { from, to, weight, slope }
+    }
+
+    pub fn mix(&self, property: impl Fn(&Biome) -> f32) -> f32 { property(&BIOMES[self.from]) * (1.0 - self.weight) + property(&BIOMES[self.to]) * self.weight }
+
+    //derivative of mix(property) by z
+    pub fn mix_slope(&self, property: impl Fn(&Biome) -> f32) -> f32 { (property(&BIOMES[self.to]) - property(&BIOMES[self.from])) * self.slope }
+
+    pub fn mix_color(&self, color: impl Fn(&Biome) -> Vec3) -> Vec3 { color(&BIOMES[self.from]) * (1.0 - self.weight) + color(&BIOMES[self.to]) * self.weight }
+}
+
+//in the order of the layers they are loaded into
+pub fn textures() -> [&'static str; BIOMES.len()] { BIOMES.map(|biome| biome.texture) }
+
+pub fn ambient(z: f32) -> (f32, f32, f32) { Blend::at(z).mix_color(|biome| Vec3::from(biome.ambient)).into() }
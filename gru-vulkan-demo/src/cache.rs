@@ -0,0 +1,218 @@
+use std::{fs::File, io::{self, Read, Write, BufReader, BufWriter}};
+use gru_misc::math::*;
+use crate::{mold, cave, config, pickup, consts, biome};
+
+const MAGIC: &[u8; 4] = b"CJBC";
+const VERSION: u32 = 10;
+
+/* file layout (little endian)
+    header: magic, hash: u64, z: i32
+    meshes, z.lod.block: lod: u32,
+        vertices: count: u32, per vertex position: 3 f32, normal: 3 f32, color: 3 f32
+        indices: count: u32, per index u32
+    pickups, z.pickups: count: u32, per pickup kind: u8, pos: 3 f32, color: 3 f32
+*/
+
+//generated block meshes and pickups on disk, one directory per cave
+#[derive(Clone)]
+pub struct Cache
+{
+    dir: String,
+    hash: u64
+}
+
+impl Cache
+{
+    pub fn new(config: &config::Config) -> Option<Self>
+    {
+        let root = config.cache.as_ref()?;
+        //everything that changes what a block looks like has to go in here
+        let mut hasher = Fnv::new();
+        hasher.write(&VERSION.to_le_bytes());
+        hasher.write(&config.seed.to_le_bytes());
+        hasher.write(config.noise.name().as_bytes());
+        hasher.write(&(config.octaves as u64).to_le_bytes());
+        for param in [config.frequency, config.lacunarity, config.persistence] { hasher.write(&param.to_le_bytes()); }
+        let tunables = &config.tunables;
+        hasher.write(&tunables.cave_resolution.to_le_bytes());
+        hasher.write(&tunables.cave_gen_border_power.to_le_bytes());
+        for param in
+        [
+            tunables.cave_radius, tunables.block_length, tunables.cave_gen_bias, tunables.cave_gen_border_strength,
+            tunables.cave_gen_spawn_strength, tunables.cave_gen_spawn_decay_rate, tunables.cave_chamber_spacing, tunables.cave_tunnel_spacing,
+            tunables.cave_pillar_spacing, tunables.cave_stalactite_spacing, tunables.mesh_simplify_error, consts::MESH_WELD_EPSILON, consts::MESH_DEGENERATE_AREA, consts::MESH_SIMPLIFY_MIN_COS, consts::CAVE_GEN_GRADIENT_EPSILON, consts::CAVE_SKIRT_LENGTH, consts::CAVE_SKIRT_EPSILON, consts::FLASH_BLOCK_PROB,
+            consts::CAVE_CHAMBER_RADIUS, consts::CAVE_TUNNEL_RADIUS, consts::CAVE_TUNNEL_ANGLE, consts::CAVE_PILLAR_RADIUS, consts::CAVE_PILLAR_OFFSET,
+            consts::CAVE_STALACTITE_LENGTH, consts::CAVE_STALACTITE_RADIUS, consts::CAVE_FEATURE_STRENGTH, consts::CAVE_FEATURE_SMOOTHNESS
+        ] { hasher.write(&param.to_le_bytes()); }
+        for probability in config.probabilities { hasher.write(&probability.to_le_bytes()); }
+        hasher.write(&consts::BIOME_LENGTH.to_le_bytes());
+        hasher.write(&consts::BIOME_BLEND.to_le_bytes());
+        for biome in &biome::BIOMES
+        {
+            hasher.write(biome.texture.as_bytes());
+            for param in [biome.frequency, biome.persistence] { hasher.write(&param.to_le_bytes()); }
+            for param in [biome.bias, biome.radius, biome.palette[0].0, biome.palette[0].1, biome.palette[0].2, biome.palette[1].0, biome.palette[1].1, biome.palette[1].2] { hasher.write(&param.to_le_bytes()); }
+        }
+        let hash = hasher.0;
+        let dir = format!("{root}/{}-{}-{}-{hash:016x}", config.seed, config.noise.name(), tunables.cave_resolution);
+        //without a directory to write into the game just runs uncached
+        if let Err(err) = std::fs::create_dir_all(&dir)
+        {
+            eprintln!("{dir}: {err}, not caching blocks");
+            return None;
+        }
+        Some(Self { dir, hash })
+    }
+
+    fn mesh_path(&self, z: i32, lod: usize) -> String { format!("{}/{z}.{lod}.block", self.dir) }
+    fn pickups_path(&self, z: i32) -> String { format!("{}/{z}.pickups", self.dir) }
+
+    //a missing, stale or broken file is just a miss
+    fn open(&self, path: &str, z: i32) -> Option<Reader<BufReader<File>>>
+    {
+        let mut reader = Reader(BufReader::new(File::open(path).ok()?));
+        if reader.take::<4>()? != *MAGIC { return None; }
+        if u64::from_le_bytes(reader.take()?) != self.hash { return None; }
+        if i32::from_le_bytes(reader.take()?) != z { return None; }
+        Some(reader)
+    }
+
+    pub fn load(&self, z: i32, lod: usize) -> Option<cave::BlockMesh>
+    {
+        let mut reader = self.open(&self.mesh_path(z, lod), z)?;
+        if u32::from_le_bytes(reader.take()?) != lod as u32 { return None; }
+        let vertex_count = u32::from_le_bytes(reader.take()?);
+        let mut vertices = Vec::with_capacity(vertex_count as usize);
+        for _ in 0..vertex_count
+        {
+            vertices.push(mold::Vertex { position: reader.vec3()?, normal: reader.vec3()?, color: reader.vec3()? });
+        }
+        let index_count = u32::from_le_bytes(reader.take()?);
+        let mut indices = Vec::with_capacity(index_count as usize);
+        for _ in 0..index_count { indices.push(u32::from_le_bytes(reader.take()?)); }
+        Some(cave::BlockMesh { vertices, indices })
+    }
+
+    pub fn load_pickups(&self, z: i32) -> Option<Vec<pickup::Pickup>>
+    {
+        let mut reader = self.open(&self.pickups_path(z), z)?;
+        let count = u32::from_le_bytes(reader.take()?);
+        (0..count).map(|_|
+        {
+            let [kind] = reader.take()?;
+            if kind as usize >= pickup::KINDS.len() { return None; }
+            Some(pickup::Pickup { kind: kind as usize, pos: reader.vec3()?, color: reader.vec3()? })
+        }).collect()
+    }
+
+    pub fn store(&self, z: i32, lod: usize, mesh: &cave::BlockMesh) -> io::Result<()>
+    {
+        self.write(&self.mesh_path(z, lod), z, |file|
+        {
+            file.write_all(&(lod as u32).to_le_bytes())?;
+            file.write_all(&(mesh.vertices.len() as u32).to_le_bytes())?;
+            for vertex in &mesh.vertices
+            {
+                let (position, normal, color) = (vertex.position, vertex.normal, vertex.color);
+                floats(file, &[position.0, position.1, position.2, normal.0, normal.1, normal.2, color.0, color.1, color.2])?;
+            }
+            file.write_all(&(mesh.indices.len() as u32).to_le_bytes())?;
+            mesh.indices.iter().try_for_each(|index| file.write_all(&index.to_le_bytes()))
+        })
+    }
+
+    pub fn store_pickups(&self, z: i32, pickups: &[pickup::Pickup]) -> io::Result<()>
+    {
+        self.write(&self.pickups_path(z), z, |file|
+        {
+            file.write_all(&(pickups.len() as u32).to_le_bytes())?;
+            for pickup::Pickup { kind, pos, color } in pickups
+            {
+                file.write_all(&[*kind as u8])?;
+                floats(file, &[pos.0, pos.1, pos.2, color.0, color.1, color.2])?;
+            }
+            Ok(())
+        })
+    }
+
+    //best effort, a file that could not be written is just generated again next time
+    fn write(&self, path: &str, z: i32, body: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) -> io::Result<()>
+    {
+        //write next to the final file and rename, so readers never see half a block
+        let temp = format!("{path}.{:?}.tmp", std::thread::current().id());
+        let result = File::create(&temp).and_then(|file|
+        {
+            let mut file = BufWriter::new(file);
+            file.write_all(MAGIC)?;
+            file.write_all(&self.hash.to_le_bytes())?;
+            file.write_all(&z.to_le_bytes())?;
+            body(&mut file)?;
+            file.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()
+        }).and_then(|()| std::fs::rename(&temp, path));
+        if result.is_err() { std::fs::remove_file(&temp).ok(); }
+        result
+    }
+}
+
+fn floats(file: &mut impl Write, values: &[f32]) -> io::Result<()> { values.iter().try_for_each(|value| file.write_all(&value.to_le_bytes())) }
+
+struct Reader<R: Read>(R);
+
+impl<R: Read> Reader<R>
+{
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]>
+    {
+        let mut bytes = [0; N];
+        self.0.read_exact(&mut bytes).ok()?;
+        Some(bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> { Some(f32::from_le_bytes(self.take()?)) }
+    fn vec3(&mut self) -> Option<Vec3> { Some(Vec3(self.f32()?, self.f32()?, self.f32()?)) }
+}
+
+//FNV-1a, unlike std's hasher it is stable between builds
+struct Fnv(u64);
+
+impl Fnv
+{
+    fn new() -> Self { Self(0xcbf29ce484222325) }
+
+    fn write(&mut self, bytes: &[u8])
+    {
+        for byte in bytes
+        {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn store_best_effort()
+    {
+        let mut config = config::Config::new();
+        config.cache = Some(std::env::temp_dir().join("cave-jumper-cache-test").to_str().unwrap().to_owned());
+        let cache = Cache::new(&config).unwrap();
+        let vertex = |x: f32| mold::Vertex { position: Vec3(x, 0.0, 1.0), normal: Vec3(0.0, -1.0, 0.0), color: Vec3(0.5, 0.5, 0.5) };
+        let mesh = cave::BlockMesh { vertices: vec![vertex(0.0), vertex(1.0), vertex(2.0)], indices: vec![0, 1, 2] };
+        let pickups = [pickup::Pickup { kind: 0, pos: Vec3(1.0, 2.0, 3.0), color: Vec3(1.0, 0.0, 0.0) }];
+        cache.store(1, 0, &mesh).unwrap();
+        cache.store_pickups(1, &pickups).unwrap();
+        let loaded = cache.load(1, 0).unwrap();
+        assert_eq!(loaded.indices, mesh.indices);
+        assert_eq!(loaded.vertices.iter().map(|vertex| vertex.position.0).collect::<Vec<_>>(), [0.0, 1.0, 2.0]);
+        assert_eq!(cache.load_pickups(1).unwrap().len(), 1);
+        assert!(cache.load(1, 1).is_none() && cache.load_pickups(2).is_none());
+
+        //a directory in the way makes the rename fail, the temp file must not be left behind
+        std::fs::create_dir_all(cache.mesh_path(2, 0)).unwrap();
+        assert!(cache.store(2, 0, &mesh).is_err());
+        assert!(std::fs::read_dir(&cache.dir).unwrap().all(|entry| !entry.unwrap().file_name().to_str().unwrap().ends_with(".tmp")));
+    }
+}
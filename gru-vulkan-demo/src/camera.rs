@@ -1,5 +1,35 @@
 use gru_misc::math::*;
-use crate::{mold, consts};
+use crate::{mold, collision, tunables, consts};
+
+//what the renderer needs from a camera, so it can be interpolated between ticks
+#[derive(Clone, Copy)]
+pub struct Pose
+{
+    pub pos: Vec3,
+    pub theta: f32,
+    pub phi: f32
+}
+
+impl Pose
+{
+    pub fn lerp(self, other: Self, alpha: f32) -> Self
+    {
+        Pose
+        {
+            pos: self.pos + (other.pos - self.pos) * alpha,
+            theta: self.theta + (other.theta - self.theta) * alpha,
+            phi: self.phi + (other.phi - self.phi) * alpha
+        }
+    }
+}
+
+//where the grappling hook holds on, the rope keeps the eye from getting further away than length
+#[derive(Clone, Copy)]
+pub struct Hook
+{
+    pub anchor: Vec3,
+    pub length: f32
+}
 
 pub struct Camera
 {
@@ -14,9 +44,16 @@ pub struct Camera
     pub backward: bool,
     pub left: bool,
     pub right: bool,
+    //pulls along the rope and takes it in as the player comes closer
+    pub reel: bool,
     pub jump_cooldown: f32,
     pub control_time: f32,
+    //left of the coyote time since the ground was last touched, jumping needs some
+    pub ground_time: f32,
+    pub jump_scale: f32,
+    pub gravity_scale: f32,
     pub does_physics: bool,
+    pub hook: Option<Hook>
 }
 
 impl Camera
@@ -31,94 +68,102 @@ impl Camera
             pos: Vec3(0.0, 0.0, 0.0),
             vel: Vec3(0.0, 0.0, 0.0),
             acc: Vec3(0.0, 0.0, 0.0),
-            drag: consts::AIR_DRAG,
+            drag: tunables::get().air_drag,
             forward: false,
             backward: false,
             left: false,
             right: false,
+            reel: false,
             jump_cooldown: 0.0,
-            control_time: consts::CONTROL_TIME,
-            does_physics: true
+            control_time: tunables::get().control_time,
+            ground_time: 0.0,
+            jump_scale: 1.0,
+            gravity_scale: 1.0,
+            does_physics: true,
+            hook: None
         }
     }
 
-    pub fn corners(&self, center: Vec3) -> [Vec3; 12]
+    pub fn capsule(&self) -> collision::Capsule
     {
-        [
-            center + Vec3( consts::FIGUR_WIDTH,   consts::FIGUR_HEIGHT,  consts::FIGUR_WIDTH),
-            center + Vec3( consts::FIGUR_WIDTH,   consts::FIGUR_HEIGHT, -consts::FIGUR_WIDTH),
-            center + Vec3( consts::FIGUR_WIDTH, - consts::FIGUR_HEIGHT,  consts::FIGUR_WIDTH),
-            center + Vec3( consts::FIGUR_WIDTH, - consts::FIGUR_HEIGHT, -consts::FIGUR_WIDTH),
-            center + Vec3(-consts::FIGUR_WIDTH,   consts::FIGUR_HEIGHT,  consts::FIGUR_WIDTH),
-            center + Vec3(-consts::FIGUR_WIDTH,   consts::FIGUR_HEIGHT, -consts::FIGUR_WIDTH),
-            center + Vec3(-consts::FIGUR_WIDTH, - consts::FIGUR_HEIGHT,  consts::FIGUR_WIDTH),
-            center + Vec3(-consts::FIGUR_WIDTH, - consts::FIGUR_HEIGHT, -consts::FIGUR_WIDTH),
-            center + Vec3(-consts::FIGUR_WIDTH,                    0.0,  consts::FIGUR_WIDTH),
-            center + Vec3(-consts::FIGUR_WIDTH,                    0.0, -consts::FIGUR_WIDTH),
-            center + Vec3( consts::FIGUR_WIDTH,                    0.0,  consts::FIGUR_WIDTH),
-            center + Vec3( consts::FIGUR_WIDTH,                    0.0, -consts::FIGUR_WIDTH)
-        ]
+        collision::Capsule { radius: consts::FIGUR_WIDTH, half_length: consts::FIGUR_HEIGHT - consts::FIGUR_WIDTH }
     }
 
     pub fn build_projection(&mut self, aspect: f32)
     {
-        self.proj = Mat4::perspective_vulkan(aspect, consts::CAM_ANGLE, consts::CAM_NEAR, consts::CAM_FAR);
+        self.proj = Mat4::perspective_vulkan(aspect, consts::CAM_ANGLE, consts::CAM_NEAR, tunables::get().cam_far());
     }
 
-    pub fn get_acc(&mut self, dt: f32, mold: &impl mold::Mold)
+    pub fn get_acc(&mut self)
     {
         //acc
         if self.does_physics
         {
-            self.acc = (Vec3(0.0, consts::GRAV, 0.0)
+            self.acc = (Vec3(0.0, tunables::get().grav * self.gravity_scale, 0.0)
             //input
-            + self.input() * consts::ACCELERATION * (self.control_time / consts::CONTROL_TIME)//.ceil()
+            + self.input() * tunables::get().acceleration * (self.control_time / tunables::get().control_time)//.ceil()
             ).into();
-        } else 
-        {
-            self.acc = (self.input() * consts::ACCELERATION).into();
-        }
-        self.drag = consts::AIR_DRAG;
-        //collision
-        if self.does_physics
-        {
-            let assumed_vel = (self.vel + self.acc * dt) * self.drag.powf(dt);
-            let mut collision = false;
-            let mut collision_force = Vec3(0.0,0.0,0.0);
-            for corner in self.corners(self.pos + assumed_vel * dt).iter()
+            //reeling in
+            if let Some(hook) = self.hook && self.reel
             {
-                let force = (|v: f32| { collision = collision || v > 0.0; v.max(0.0)})(mold.value(*corner)) * consts::COLLISION_FORCE;
-                collision_force = collision_force - mold.gradient(*corner).unit() * force;
-            }
-            if collision
-            {
-                self.control_time = consts::CONTROL_TIME;
-                let vel_norm = self.vel.norm();
-                let collision_force_norm = collision_force.norm();
-                if vel_norm > 0.0 && collision_force_norm > 0.0
-                {
-                    let cos = -Vec3::dot(self.vel, collision_force) / vel_norm / collision_force.norm();
-                    //self.drag *= consts::COLLISION_DRAG;
-                    self.drag *= cos.signum().max(0.0) * cos.powi(2) * consts::COLLISION_DRAG + (1.0 - cos.powi(2)) * consts::SURFACE_DRAG;
-                    self.acc = self.acc + collision_force;
-                }
+                let to_anchor = hook.anchor - self.eye();
+                let distance = to_anchor.norm();
+                if distance > 0.0 { self.acc = self.acc + to_anchor * (tunables::get().hook_pull / distance); }
             }
+        } else 
+        {
+            self.acc = (self.input() * tunables::get().acceleration).into();
         }
+        self.drag = tunables::get().air_drag;
     }
 
     pub fn logic(&mut self, dt: f32, mold: &impl mold::Mold)
     {
-        self.get_acc(dt, mold);
-        if self.acc.norm() < consts::MIN_ACCELERATION
+        self.get_acc();
+        if self.acc.norm() < tunables::get().min_acceleration
         {
             self.acc = Vec3(0.0, 0.0, 0.0);
         }
         self.vel = (self.vel + self.acc * dt) * self.drag.powf(dt);
         //pos update
-        self.pos = self.pos + self.vel * dt;
+        if self.does_physics
+        {
+            let eye = self.eye();
+            if let Some(hook) = &mut self.hook
+            {
+                //moving away along a taut rope is taken out of the velocity, so the player swings,
+                //reeling takes in the rope as the player comes closer
+                let to_anchor = hook.anchor - eye;
+                let distance = to_anchor.norm();
+                if self.reel { hook.length = hook.length.min(distance).max(consts::HOOK_MIN_LENGTH); }
+                if distance > 0.0
+                {
+                    let dir = to_anchor * (1.0 / distance);
+                    let slack = (distance - hook.length) / dt;
+                    let along = Vec3::dot(self.vel, dir);
+                    if along < slack { self.vel = self.vel + dir * (slack - along); }
+                }
+            }
+            let slide = collision::move_and_slide(mold, &self.capsule(), self.pos, self.vel, dt);
+            self.pos = slide.pos;
+            self.vel = slide.vel;
+            if slide.grounded { self.ground_time = consts::COYOTE_TIME; }
+            //walls and ceilings give control back as well, jumping still needs the ground
+            if slide.contact
+            {
+                self.vel = self.vel * tunables::get().surface_drag.powf(dt);
+                self.control_time = tunables::get().control_time;
+            }
+        } else
+        {
+            self.pos = self.pos + self.vel * dt;
+            //noclip lets go of the hook
+            self.hook = None;
+        }
 
         self.jump_cooldown = (self.jump_cooldown - dt).max(0.0);
         self.control_time = (self.control_time - dt).max(0.0);
+        self.ground_time = (self.ground_time - dt).max(0.0);
     }
 
     pub fn input(&mut self) -> Vec3
@@ -139,19 +184,111 @@ impl Camera
         Vec3(input_acc.0, input_acc.1, input_acc.2)
     }
 
+    pub fn look(&mut self, dx: f32, dy: f32)
+    {
+        self.phi += tunables::get().mouse_sensitivity * dx;
+        self.theta -= tunables::get().mouse_sensitivity * dy;
+        self.theta = self.theta.clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+    }
+
     pub fn jump(&mut self)
     {
-        if self.jump_cooldown == 0.0 && self.control_time > 0.0
+        if self.jump_cooldown == 0.0 && self.ground_time > 0.0
         {
-            self.vel.1 -= consts::JUMP;
-            self.jump_cooldown = consts::JUMP_COOLDOWN;
+            self.vel.1 -= tunables::get().jump * self.jump_scale;
+            self.jump_cooldown = tunables::get().jump_cooldown;
         }
     }
 
-    pub fn mats(&self) -> (Mat4, Mat4)
+    //fires along the view direction from the eye, or lets go if the hook already holds on
+    pub fn grapple(&mut self, mold: &impl mold::Mold)
+    {
+        if self.hook.take().is_some() || !self.does_physics { return; }
+        let rot = Mat4::rotation_y(self.phi) * Mat4::rotation_x(self.theta);
+        let dir = rot * Vec4(0.0, 0.0, 1.0, 0.0);
+        if let Some(hit) = collision::ray_cast(mold, self.eye(), Vec3(dir.0, dir.1, dir.2), tunables::get().hook_range)
+        {
+            self.hook = Some(Hook { anchor: hit.pos, length: hit.distance.max(consts::HOOK_MIN_LENGTH) });
+        }
+    }
+
+    //the rope is tied to the eye, where the hook was fired from
+    pub fn eye(&self) -> Vec3 { self.pos - Vec3(0.0, consts::EYE_HEIGHT, 0.0) }
+
+    pub fn pose(&self) -> Pose { Pose { pos: self.pos, theta: self.theta, phi: self.phi } }
+
+    pub fn mats(&self, pose: Pose) -> (Mat4, Mat4)
     {
-        let rot = Mat4::rotation_x(-self.theta) * Mat4::rotation_y(-self.phi);
-        let trans = Mat4::translation(Vec3(-self.pos.0, -self.pos.1 + consts::EYE_HEIGHT, -self.pos.2));
+        let rot = Mat4::rotation_x(-pose.theta) * Mat4::rotation_y(-pose.phi);
+        let trans = Mat4::translation(Vec3(-pose.pos.0, -pose.pos.1 + consts::EYE_HEIGHT, -pose.pos.2));
         (self.proj, rot * trans)
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    //upwards speed right after trying to jump, the player starts at pos and spends `air` seconds off the ground first
+    fn jump_speed(pos: Vec3, air: f32) -> f32
+    {
+        let rock = mold::Sphere(10.0);
+        let mut cam = Camera::new();
+        cam.pos = pos;
+        cam.logic(1.0 / 60.0, &rock);
+        cam.pos = Vec3(0.0, -20.0, 0.0);
+        cam.logic(air, &rock);
+        cam.jump();
+        -cam.vel.1
+    }
+
+    #[test]
+    fn rope()
+    {
+        tunables::init_default();
+        let rock = mold::Sphere(1.0);
+        let dt = 1.0 / 120.0;
+        let mut cam = Camera::new();
+        cam.pos = Vec3(0.0, consts::EYE_HEIGHT, -8.0);
+        cam.grapple(&rock);
+        let hook = cam.hook.unwrap();
+        assert!((hook.anchor - Vec3(0.0, 0.0, -1.0)).norm() < 1e-2 && (hook.length - 7.0).abs() < 1e-2, "{:?} {}", hook.anchor, hook.length);
+        //the rope starts out exactly taut, so the first tick is just gravity
+        cam.logic(dt, &rock);
+        assert!(cam.vel.norm() <= tunables::get().grav * dt * 1.01, "{:?}", cam.vel);
+
+        //hanging on it the player swings below the anchor, the rope keeps its length
+        for _ in 0..240 { cam.logic(dt, &rock); }
+        let distance = (hook.anchor - cam.eye()).norm();
+        assert!(cam.hook.unwrap().length == hook.length && distance < hook.length + 0.05, "{distance}");
+        assert!(cam.eye().1 > 1.0, "{:?}", cam.eye());
+
+        //reeling pulls in and takes up the rope
+        cam.reel = true;
+        for _ in 0..120 { cam.logic(dt, &rock); }
+        assert!(cam.hook.unwrap().length < hook.length - 2.0 && (hook.anchor - cam.eye()).norm() < hook.length - 2.0);
+
+        //firing again lets go, out of range nothing is hit
+        cam.grapple(&rock);
+        assert!(cam.hook.is_none());
+        cam.pos = Vec3(0.0, consts::EYE_HEIGHT, -30.0);
+        cam.grapple(&rock);
+        assert!(cam.hook.is_none());
+    }
+
+    #[test]
+    fn jump_from_ground_only()
+    {
+        tunables::init_default();
+        let jump = tunables::get().jump;
+        let top = Vec3(0.0, -10.0 - consts::FIGUR_HEIGHT, 0.0);
+        assert!(jump_speed(top, 0.0) > 0.5 * jump);
+        //just ran over the edge
+        assert!(jump_speed(top, 0.5 * consts::COYOTE_TIME) > 0.5 * jump);
+        assert!(jump_speed(top, 2.0 * consts::COYOTE_TIME) < 0.5 * jump);
+        //touching a wall or a ceiling gives control back, but no jump
+        assert!(jump_speed(Vec3(10.0 + consts::FIGUR_WIDTH - 0.05, 0.0, 0.0), 0.0) < 0.5 * jump);
+        assert!(jump_speed(Vec3(0.0, 10.0 + consts::FIGUR_HEIGHT - 0.05, 0.0), 0.0) < 0.5 * jump);
+    }
+}
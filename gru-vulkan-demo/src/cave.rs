@@ -1,7 +1,9 @@
 use super::*;
 use super::Vertex;
 use gru_misc::marching_cubes;
-use rand::distributions::{Distribution, Uniform};
+use noise::NoiseFn;
+use rand::{SeedableRng, distributions::{Distribution, Uniform}};
+use std::{cmp::Reverse, collections::BinaryHeap};
 
 pub struct CylinderBlock
 {
@@ -9,25 +11,15 @@ pub struct CylinderBlock
 	pub vertex_view: BufferView<Vertex>,
 	pub index_view: BufferView<u32>,
 	pub z: i32,
-    pub flashes: Vec<(Vec3, Vec3)>
+	pub lod: usize
 }
 
 impl CylinderBlock
 {
-    pub fn new(device: &Device, command_pool: &CommandPool, queue: &Arc<Mutex<Queue>>, mold: &impl mold::Mold, z: i32) -> Self
+    pub fn new(device: &Device, command_pool: &CommandPool, queue: &Arc<Mutex<Queue>>, mesh: &BlockMesh, z: i32, lod: usize) -> Self
     {
-        let config = marching_cubes::Config
-        {
-            offset: Vec3(0.0, 0.0, z as f32 * consts::BLOCK_LENGTH),
-            radii: Vec3(consts::CAVE_RADIUS * 2.0, consts::CAVE_RADIUS * 2.0, consts::BLOCK_LENGTH / 2.0),
-            resolutions: (consts::CAVE_RESOLUTION, consts::CAVE_RESOLUTION, (consts::CAVE_RESOLUTION as f32 / consts::CAVE_RADIUS * consts::BLOCK_LENGTH / 4.0) as u32)
-        };
-        let (vert, indices) = marching_cubes::build(|pos| mold.value(pos), config);
-        let vertices: Vec<_> = vert.into_iter().map(|v|
-        {
-            let v = mold.new_vertex(v);
-            Vertex { position: v.position.into(), normal: v.normal.into(), tex_coords: v.coords.into() }
-        }).collect();
+        let indices = &mesh.indices;
+        let vertices: Vec<_> = mesh.vertices.iter().map(|v| Vertex { position: v.position.into(), normal: v.normal.into(), color: v.color.into() }).collect();
         let mut vertex_buffer_layout = device.new_buffer_type();
         let vertex_view = vertex_buffer_layout.add_attributes(vertices.len() as u32);
         let index_view = vertex_buffer_layout.add_indices(indices.len() as u32);
@@ -38,111 +30,635 @@ impl CylinderBlock
             {
                let mut buffer_map = vertex_buffer_temp.map();
                buffer_map.write_attributes(&vertex_view, 0, &vertices);
-               buffer_map.write_indices(&index_view, 0, &indices);
+               buffer_map.write_indices(&index_view, 0, indices);
             }
             let vertex_buffer = device.new_buffer(&vertex_buffer_layout, BufferUsage::Static);
             command_pool.new_command_buffer().copy_buffer(&queue.lock().unwrap(), &vertex_buffer_temp, &vertex_buffer, device.new_fence(false)).mark.wait();
             vertex_buffer
         };
-        //Generate Flashes
-        let mut flashes = Vec::with_capacity(1);
-        let range = Uniform::from(-consts::CAVE_RADIUS..consts::CAVE_RADIUS);
-        let mut rng = rand::thread_rng();
-        if range.sample(&mut rng) / consts::CAVE_RADIUS / 2.0 + 0.5 < consts::FLASH_BLOCK_PROB
-        {
-            let mut pos = Vec3(range.sample(&mut rng), range.sample(&mut rng), (z as f32 + range.sample(&mut rng) / consts::CAVE_RADIUS / 2.0) * consts::BLOCK_LENGTH);
-            while mold.value(pos) < 0.0 &&  mold.value(pos) < -0.1
+        Self { buffer, vertex_view, index_view, z, lod }
+    }
+}
+
+pub struct BlockMesh
+{
+    pub vertices: Vec<mold::Vertex>,
+    pub indices: Vec<u32>
+}
+
+//0 is full detail, every further level kicks in CAVE_LOD_DISTANCES blocks away from the camera
+pub fn lod(z: i32, cam_norm: f32) -> usize
+{
+    let distance = (z as f32 - cam_norm).abs();
+    consts::CAVE_LOD_DISTANCES.iter().filter(|lod_distance| distance >= **lod_distance).count()
+}
+
+//with the counts before and after cleaning the mesh up
+pub fn build_mesh(mold: &impl mold::Mold, z: i32, lod: usize) -> (BlockMesh, simplify::Counts)
+{
+    let tunables = tunables::get();
+    let resolution = tunables.lod_resolution(lod);
+    let config = marching_cubes::Config
+    {
+        offset: Vec3(0.0, 0.0, z as f32 * tunables.block_length),
+        radii: Vec3(tunables.cave_radius * 2.0, tunables.cave_radius * 2.0, tunables.block_length / 2.0),
+        resolutions: (resolution, resolution, (resolution as f32 / tunables.cave_radius * tunables.block_length / 4.0) as u32)
+    };
+    let (vertices, indices) = marching_cubes::build(|pos| mold.value(pos), config);
+    let mut mesh = BlockMesh { vertices: vertices.into_iter().map(|v| mold.new_vertex(v)).collect(), indices };
+    let counts = simplify::process(&mut mesh);
+    add_skirts(&mut mesh, z, tunables.cave_radius * 4.0 / resolution as f32 * consts::CAVE_SKIRT_LENGTH);
+    (mesh, counts)
+}
+
+//neighbours of different detail don't meet exactly at their shared face,
+//so every edge lying on a face gets a strip hanging back into the rock that covers the crack
+fn add_skirts(mesh: &mut BlockMesh, z: i32, length: f32)
+{
+    let faces = [z as f32 - 0.5, z as f32 + 0.5].map(|face| face * tunables::get().block_length);
+    let on_face = |v: &mold::Vertex| faces.iter().any(|face| (v.position.2 - face).abs() < consts::CAVE_SKIRT_EPSILON);
+    let base = mesh.vertices.len() as u32;
+    let mut skirt_vertices = Vec::new();
+    let mut skirt_index = HashMap::new();
+    let mut skirt_indices = Vec::new();
+    for triangle in mesh.indices.chunks_exact(3)
+    {
+        for (a, b) in [(triangle[0], triangle[1]), (triangle[1], triangle[2]), (triangle[2], triangle[0])]
+        {
+            if !on_face(&mesh.vertices[a as usize]) || !on_face(&mesh.vertices[b as usize]) { continue; }
+            let [a_skirt, b_skirt] = [a, b].map(|i| *skirt_index.entry(i).or_insert_with(||
             {
-                pos.1 += 0.001;
+                let v = &mesh.vertices[i as usize];
+                skirt_vertices.push(mold::Vertex { position: v.position - v.normal * length, normal: v.normal, color: v.color });
+                base + skirt_vertices.len() as u32 - 1
+            }));
+            //wound against the edge, like the neighbouring triangle would be
+            skirt_indices.extend_from_slice(&[b, a, a_skirt, b, a_skirt, b_skirt]);
+        }
+    }
+    mesh.vertices.extend(skirt_vertices);
+    mesh.indices.extend(skirt_indices);
+}
+
+//a pure function of (seed, z): every kind gets one chance per block, its candidates are drawn until one lies in open space, which then drops onto the floor below
+pub fn place_pickups(mold: &impl mold::Mold, seed: u32, z: i32, probabilities: &[f32]) -> Vec<pickup::Pickup>
+{
+    let tunables = tunables::get();
+    let mut pickups = Vec::with_capacity(1);
+    let range = Uniform::from(-tunables.cave_radius..tunables.cave_radius);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(((seed as u64) << 32) | z as u32 as u64);
+    for (kind, probability) in probabilities.iter().enumerate()
+    {
+        if range.sample(&mut rng) / tunables.cave_radius / 2.0 + 0.5 >= *probability { continue; }
+        for _ in 0..consts::FLASH_PLACEMENT_TRIES
+        {
+            let pos = Vec3(range.sample(&mut rng), range.sample(&mut rng), (z as f32 + range.sample(&mut rng) / tunables.cave_radius / 2.0) * tunables.block_length);
+            if let Some(pos) = drop_to_floor(mold, pos)
+            {
+                pickups.push(pickup::Pickup::new(kind, pos));
+                break;
             }
-            if mold.value(pos) < 0.0 { flashes.push((pos, flash::FlashMold.color(pos))); }
-        };
-        Self { buffer, vertex_view, index_view, z, flashes }
+        }
+    }
+    pickups
+}
+
+//casts down (+y) from an open point and stops FLASH_FLOOR_HEIGHT above the surface
+fn drop_to_floor(mold: &impl mold::Mold, pos: Vec3) -> Option<Vec3>
+{
+    if mold.value(pos) >= 0.0 { return None; }
+    let hit = collision::ray_cast(mold, pos, Vec3(0.0, 1.0, 0.0), tunables::get().cave_radius * 2.0 - pos.1)?;
+    let pos = hit.pos - Vec3(0.0, consts::FLASH_FLOOR_HEIGHT, 0.0);
+    //walls and ceilings would leave the flash hanging in the air, and so would a wall closer than the floor
+    if hit.normal.1 >= -consts::GROUND_SLOPE || mold.value(pos) >= 0.0 { return None; }
+    let (_, normal) = collision::closest_surface(mold, pos)?;
+    (normal.1 < -consts::GROUND_SLOPE).then_some(pos)
+}
+
+//requests waiting for a worker, the closest one to the camera goes next
+struct Requests
+{
+    //the latest detail level asked for per block
+    pending: HashMap<i32, usize>,
+    //by distance to the camera's block, entries no longer pending are skipped when they come up
+    queue: BinaryHeap<Reverse<(u32, i32)>>,
+    cam_block: i32,
+    closed: bool
+}
+
+impl Requests
+{
+    fn key(&self, z: i32) -> Reverse<(u32, i32)> { Reverse(((z - self.cam_block).unsigned_abs(), z)) }
+
+    fn push(&mut self, z: i32, lod: usize)
+    {
+        self.pending.insert(z, lod);
+        let key = self.key(z);
+        self.queue.push(key);
+    }
+
+    fn pop(&mut self) -> Option<(i32, usize)>
+    {
+        while let Some(Reverse((_, z))) = self.queue.pop()
+        {
+            if let Some(lod) = self.pending.remove(&z) { return Some((z, lod)); }
+        }
+        None
+    }
+
+    //the camera moved into another block, so every distance changes
+    fn focus(&mut self, cam_norm: f32)
+    {
+        let cam_block = cam_norm.round() as i32;
+        if cam_block == self.cam_block { return; }
+        self.cam_block = cam_block;
+        self.queue = self.pending.keys().map(|z| self.key(*z)).collect();
     }
 }
 
+#[derive(Clone, Copy, Default)]
+pub struct GeneratorStats
+{
+    pub generated: u32,
+    pub cancelled: u32,
+    pub total_time: std::time::Duration,
+    pub max_time: std::time::Duration,
+    pub max_queued: usize,
+    //only of the blocks that were built, not loaded from the cache
+    pub counts: simplify::Counts
+}
+
+//one pool of workers, each with its own mold, sharing a queue of requested blocks
 pub struct BlockGenerator
 {
-    t_request: mpsc::Sender<i32>,
-    r_block: mpsc::Receiver<CylinderBlock>
+    requests: Arc<(Mutex<Requests>, std::sync::Condvar)>,
+    stats: Arc<Mutex<GeneratorStats>>,
+    r_block: mpsc::Receiver<CylinderBlock>,
+    workers: Vec<std::thread::JoinHandle<()>>
 }
 
 impl BlockGenerator
 {
-    pub fn new(device: &Device, queue_family_info: &QueueFamilyInfo, mold: impl mold::Mold + std::marker::Send + 'static) -> Self
+    pub fn new<M: mold::Mold + std::marker::Send + 'static>(device: &Device, queue_family_info: &QueueFamilyInfo, mold_gen: impl Fn() -> M, cache: Option<cache::Cache>) -> Self
     {
-        let (t_request, r_request) = std::sync::mpsc::channel();
+        let requests = Arc::new((Mutex::new(Requests { pending: HashMap::new(), queue: BinaryHeap::new(), cam_block: 0, closed: false }), std::sync::Condvar::new()));
+        let stats = Arc::new(Mutex::new(GeneratorStats::default()));
         let (t_block, r_block) = std::sync::mpsc::channel();
-        let device = device.clone();
-        let queue_family_info = queue_family_info.clone();
-        std::thread::spawn(move ||
+        //leave one core for the render loop
+        let worker_count = std::thread::available_parallelism().map_or(1, |cores| cores.get().saturating_sub(1).max(1));
+        let workers = (0..worker_count).map(|_|
         {
-            let graphic_queue_family = device.get_queue_family(&queue_family_info);
-            let command_pool = device.new_command_pool(graphic_queue_family);
-            let queue = graphic_queue_family.get_queue(0);
+            let (device, queue_family_info, mold, cache) = (device.clone(), queue_family_info.clone(), mold_gen(), cache.clone());
+            let (requests, stats, t_block) = (requests.clone(), stats.clone(), t_block.clone());
+            std::thread::spawn(move ||
+            {
+                let graphic_queue_family = device.get_queue_family(&queue_family_info);
+                let command_pool = device.new_command_pool(graphic_queue_family);
+                let queue = graphic_queue_family.get_queue(0);
 
-            for request in r_request.iter()
+                while let Some((z, lod)) = Self::next_request(&requests)
+                {
+                    let start = std::time::Instant::now();
+                    let cached = cache.as_ref().and_then(|cache| cache.load(z, lod));
+                    let (mesh, counts) = match cached
+                    {
+                        Some(mesh) => (mesh, simplify::Counts::default()),
+                        None =>
+                        {
+                            let (mesh, counts) = build_mesh(&mold, z, lod);
+                            if let Some(Err(err)) = cache.as_ref().map(|cache| cache.store(z, lod, &mesh))
+                            {
+                                eprintln!("block {z} lod {lod} not cached: {err}");
+                            }
+                            (mesh, counts)
+                        }
+                    };
+                    let block = CylinderBlock::new(&device, &command_pool, &queue, &mesh, z, lod);
+                    let time = start.elapsed();
+                    {
+                        let mut stats = stats.lock().unwrap();
+                        stats.generated += 1;
+                        stats.total_time += time;
+                        stats.max_time = stats.max_time.max(time);
+                        stats.counts += counts;
+                    }
+                    t_block.send(block).ok();
+                }
+            })
+        }).collect();
+        Self { requests, stats, r_block, workers }
+    }
+
+    //blocks until there is work, None once the pool is shut down
+    fn next_request(requests: &(Mutex<Requests>, std::sync::Condvar)) -> Option<(i32, usize)>
+    {
+        let (requests, condvar) = requests;
+        let mut requests = condvar.wait_while(requests.lock().unwrap(), |requests| requests.pending.is_empty() && !requests.closed).unwrap();
+        if requests.closed { return None; }
+        requests.pop()
+    }
+
+    //a newer request for the same block replaces the queued one
+    pub fn request(&self, z: i32, lod: usize)
+    {
+        let (requests, condvar) = &*self.requests;
+        let mut requests = requests.lock().unwrap();
+        requests.push(z, lod);
+        let queued = requests.pending.len();
+        let mut stats = self.stats.lock().unwrap();
+        stats.max_queued = stats.max_queued.max(queued);
+        condvar.notify_one();
+    }
+
+    //returns whether the block was still waiting, blocks already being generated arrive anyway
+    pub fn cancel(&self, z: i32) -> bool
+    {
+        let cancelled = self.requests.0.lock().unwrap().pending.remove(&z).is_some();
+        if cancelled { self.stats.lock().unwrap().cancelled += 1; }
+        cancelled
+    }
+
+    pub fn focus(&self, cam_norm: f32) { self.requests.0.lock().unwrap().focus(cam_norm); }
+    pub fn stats(&self) -> GeneratorStats { *self.stats.lock().unwrap() }
+    pub fn receive(&self) -> mpsc::TryIter<CylinderBlock> { self.r_block.try_iter() }
+
+    pub fn shutdown(self) -> GeneratorStats
+    {
+        {
+            let (requests, condvar) = &*self.requests;
+            requests.lock().unwrap().closed = true;
+            condvar.notify_all();
+        }
+        for worker in self.workers { worker.join().unwrap(); }
+        for _ in self.r_block.try_iter() {}
+        self.stats()
+    }
+}
+
+//value and gradient of a noise function
+pub trait NoiseGradient: noise::NoiseFn<[f64; 3]>
+{
+    fn get_gradient(&self, point: [f64; 3]) -> (f64, [f64; 3]) { central_differences(self, point) }
+}
+
+pub fn central_differences(fun: &(impl noise::NoiseFn<[f64; 3]> + ?Sized), point: [f64; 3]) -> (f64, [f64; 3])
+{
+    let epsilon = consts::CAVE_GEN_GRADIENT_EPSILON as f64;
+    let mut gradient = [0.0; 3];
+    for (axis, gradient) in gradient.iter_mut().enumerate()
+    {
+        let (mut above, mut below) = (point, point);
+        above[axis] += epsilon;
+        below[axis] -= epsilon;
+        *gradient = (fun.get(above) - fun.get(below)) / consts::CAVE_GEN_GRADIENT_EPSILON_2 as f64;
+    }
+    (fun.get(point), gradient)
+}
+
+pub enum CaveNoise
+{
+    Fractal(perlin::Fractal),
+    Perlin(perlin::Perlin, f64),
+    HybridMulti(noise::HybridMulti),
+    Value(noise::Value, f64),
+    OpenSimplex(noise::OpenSimplex, f64),
+    SuperSimplex(noise::SuperSimplex, f64)
+}
+
+impl noise::NoiseFn<[f64; 3]> for CaveNoise
+{
+    fn get(&self, point: [f64; 3]) -> f64
+    {
+        //the single octave noises have no frequency of their own
+        match self
+        {
+            Self::Fractal(fun) => fun.value(point),
+            Self::Perlin(fun, frequency) => fun.value3(point.map(|x| x * frequency)),
+            Self::HybridMulti(fun) => fun.get(point),
+            Self::Value(fun, frequency) => fun.get(point.map(|x| x * frequency)),
+            Self::OpenSimplex(fun, frequency) => fun.get(point.map(|x| x * frequency)),
+            Self::SuperSimplex(fun, frequency) => fun.get(point.map(|x| x * frequency))
+        }
+    }
+}
+
+impl NoiseGradient for CaveNoise
+{
+    fn get_gradient(&self, point: [f64; 3]) -> (f64, [f64; 3])
+    {
+        match self
+        {
+            Self::Fractal(fun) => fun.get(point),
+            Self::Perlin(fun, frequency) =>
             {
-                let block = CylinderBlock::new(&device, &command_pool, &queue, &mold, request);
-                t_block.send(block).ok();
-            }
+                let (value, gradient) = fun.get3(point.map(|x| x * frequency));
+                (value, gradient.map(|x| x * frequency))
+            },
+            _ => central_differences(self, point)
+        }
+    }
+}
+
+//shapes composed into the noise, laid out around the winding tunnel center
+pub struct Features
+{
+    carved: Box<dyn mold::Mold + Send>,
+    added: Box<dyn mold::Mold + Send>
+}
+
+impl Features
+{
+    //None if every kind is turned off, so the cave stays as it was
+    pub fn new(perlin: &perlin::Perlin) -> Option<Self>
+    {
+        use mold::{Capsule, Combine, Op, Repeat, Rotate, Scale, Sphere, Translate, Warp};
+        let tunables = tunables::get();
+        let spacings = [tunables.cave_chamber_spacing, tunables.cave_tunnel_spacing, tunables.cave_pillar_spacing, tunables.cave_stalactite_spacing];
+        if spacings.iter().all(|spacing| *spacing == 0.0) { return None; }
+        let radius = tunables.cave_radius;
+        let along = |spacing: f32| Vec3(0.0, 0.0, spacing);
+
+        let chambers = (tunables.cave_chamber_spacing > 0.0).then(|| Repeat { mold: Sphere(consts::CAVE_CHAMBER_RADIUS), period: along(tunables.cave_chamber_spacing) });
+        //from the center out through the wall, halfway between chambers
+        let tunnels = (tunables.cave_tunnel_spacing > 0.0).then(|| Repeat
+        {
+            mold: Translate
+            {
+                mold: Rotate::new(Capsule { a: Vec3(0.0, 0.0, 0.0), b: Vec3(radius * 2.0, 0.0, 0.0), radius: consts::CAVE_TUNNEL_RADIUS }, Vec3(0.0, 1.0, 0.0), -consts::CAVE_TUNNEL_ANGLE),
+                offset: along(tunables.cave_tunnel_spacing / 2.0)
+            },
+            period: along(tunables.cave_tunnel_spacing)
         });
-        Self { t_request, r_block }
+        //floor to ceiling, built in cave radii
+        let pillars = (tunables.cave_pillar_spacing > 0.0).then(|| Repeat
+        {
+            mold: Translate
+            {
+                mold: Scale { mold: Capsule { a: Vec3(0.0, -1.0, 0.0), b: Vec3(0.0, 1.0, 0.0), radius: consts::CAVE_PILLAR_RADIUS / radius }, factor: radius },
+                offset: Vec3(consts::CAVE_PILLAR_OFFSET * radius, 0.0, 0.0)
+            },
+            period: along(tunables.cave_pillar_spacing)
+        });
+        //hanging from the ceiling, which is at -y
+        let stalactites = (tunables.cave_stalactite_spacing > 0.0).then(|| Repeat
+        {
+            mold: Capsule { a: Vec3(0.0, -radius, 0.0), b: Vec3(0.0, consts::CAVE_STALACTITE_LENGTH - radius, 0.0), radius: consts::CAVE_STALACTITE_RADIUS },
+            period: Vec3(tunables.cave_stalactite_spacing, 0.0, tunables.cave_stalactite_spacing)
+        });
+
+        let carved = Combine { a: chambers, b: tunnels, op: Op::Union, smoothness: consts::CAVE_FEATURE_SMOOTHNESS };
+        let added = Combine { a: pillars, b: stalactites, op: Op::Union, smoothness: consts::CAVE_FEATURE_SMOOTHNESS };
+        Some(Self
+        {
+            carved: Box::new(Warp { mold: carved, field: Self::center(perlin.clone()) }),
+            added: Box::new(Warp { mold: added, field: Self::center(perlin.clone()) })
+        })
     }
 
-    pub fn request(&self, z: i32) { self.t_request.send(z).ok(); }
-    pub fn receive(&self) -> mpsc::TryIter<CylinderBlock> { self.r_block.try_iter() }
+    //moves the tunnel center onto the z axis, like the border term in Cave::value
+    fn center(perlin: perlin::Perlin) -> impl Fn(Vec3) -> (Vec3, [Vec3; 3]) + Send
+    {
+        let radius = tunables::get().cave_radius;
+        move |pos|
+        {
+            let (center_x, center_x_gradient) = perlin.get2([pos.2 as f64 * 0.01, 0.0]);
+            let (center_y, center_y_gradient) = perlin.get2([0.0, pos.2 as f64 * 0.01]);
+            let displacement = Vec3(-radius * center_x as f32, -radius * center_y as f32, 0.0);
+            let gradients = [Vec3(0.0, 0.0, -radius * 0.01 * center_x_gradient[0] as f32), Vec3(0.0, 0.0, -radius * 0.01 * center_y_gradient[1] as f32), Vec3(0.0, 0.0, 0.0)];
+            (displacement, gradients)
+        }
+    }
 
-    pub fn shutdown(self)
+    //carves the chambers and tunnels out of the cave value, then adds the pillars and stalactites
+    fn apply(&self, cave: (f32, Vec3), pos: Vec3) -> (f32, Vec3)
     {
-        std::mem::drop(self.t_request);
-        for _ in self.r_block.into_iter() {}
+        let scaled = |(value, gradient): (f32, Vec3)| (value * consts::CAVE_FEATURE_STRENGTH, gradient * consts::CAVE_FEATURE_STRENGTH);
+        let smoothness = consts::CAVE_FEATURE_SMOOTHNESS * consts::CAVE_FEATURE_STRENGTH;
+        let carved = mold::Op::Subtraction.apply(cave, scaled(self.carved.value_gradient(pos)), smoothness);
+        mold::Op::Union.apply(carved, scaled(self.added.value_gradient(pos)), smoothness)
     }
 }
 
-pub struct Cave<T: noise::NoiseFn<[f64; 3]>>
+pub struct Cave<T: NoiseGradient>
 {
-    pub fun: T,
-    pub perlin: noise::Perlin,
+    //one per biome
+    pub funs: Vec<T>,
+    pub perlin: perlin::Perlin,
     pub bias: f32,
+    pub features: Option<Features>,
     x0: f32,
     y0: f32
 }
 
-impl<T: noise::NoiseFn<[f64; 3]>> Cave<T>
+impl<T: NoiseGradient> Cave<T>
 {
-    pub fn new(fun: T, perlin: noise::Perlin, bias: f32) -> Self
+    pub fn new(funs: Vec<T>, perlin: perlin::Perlin, bias: f32) -> Self
     {
-        let (x0, y0) = (consts::CAVE_RADIUS * perlin.get([0.0, 0.0]) as f32, consts::CAVE_RADIUS * perlin.get([0.0, 0.0]) as f32);
-        Self { fun, perlin, bias, x0, y0 }
+        assert_eq!(funs.len(), biome::BIOMES.len());
+        let radius = tunables::get().cave_radius;
+        let (x0, y0) = (radius * perlin.get2([0.0, 0.0]).0 as f32, radius * perlin.get2([0.0, 0.0]).0 as f32);
+        let features = Features::new(&perlin);
+        Self { funs, perlin, bias, features, x0, y0 }
     }
 
     pub fn x0(&self) -> f32 { self.x0 }
     pub fn y0(&self) -> f32 { self.y0 }
+
+    //the noises of both biomes, the second is only needed within a transition
+    fn noise(&self, blend: biome::Blend, point: [f64; 3]) -> f32
+    {
+        let from = self.funs[blend.from].get(point) as f32;
+        if blend.weight > 0.0 { from * (1.0 - blend.weight) + self.funs[blend.to].get(point) as f32 * blend.weight } else { from }
+    }
+
+    //the noise, border and spawn terms without any features
+    fn base_value(&self, Vec3(x, y, z): Vec3) -> f32
+    {
+        let tunables = tunables::get();
+        let blend = biome::Blend::at(z);
+        let radius = tunables.cave_radius * blend.mix(|biome| biome.radius);
+        self.noise(blend, [x as f64, y as f64, z as f64])
+      + ((x - tunables.cave_radius * self.perlin.get2([z as f64 * 0.01, 0.0]).0 as f32).abs().max((y - tunables.cave_radius * self.perlin.get2([0.0, z as f64 * 0.01]).0 as f32).abs()).max(-z) / radius).powi(tunables.cave_gen_border_power) * tunables.cave_gen_border_strength
+      - tunables.cave_gen_spawn_strength * (tunables.cave_gen_spawn_decay_rate * ((x - self.x0)*(x - self.x0) + (y - self.y0)*(y - self.y0) + z*z)).exp()
+      + self.bias + blend.mix(|biome| biome.bias)
+    }
+
+    //same terms as base_value, differentiated by hand
+    fn base_value_gradient(&self, Vec3(x, y, z): Vec3) -> (f32, Vec3)
+    {
+        let tunables = tunables::get();
+        let blend = biome::Blend::at(z);
+        let (noise, noise_gradient) =
+        {
+            let point = [x as f64, y as f64, z as f64];
+            let (from, from_gradient) = self.funs[blend.from].get_gradient(point);
+            let (to, to_gradient) = if blend.weight > 0.0 { self.funs[blend.to].get_gradient(point) } else { (from, from_gradient) };
+            let mix = |from: f64, to: f64| from as f32 * (1.0 - blend.weight) + to as f32 * blend.weight;
+            let gradient = Vec3(mix(from_gradient[0], to_gradient[0]), mix(from_gradient[1], to_gradient[1]), mix(from_gradient[2], to_gradient[2]));
+            (mix(from, to), gradient + Vec3(0.0, 0.0, (to - from) as f32 * blend.slope))
+        };
+        //border: max norm distance to the winding tunnel center, relative to the biome's radius
+        let (center_x, center_x_gradient) = self.perlin.get2([z as f64 * 0.01, 0.0]);
+        let (center_y, center_y_gradient) = self.perlin.get2([0.0, z as f64 * 0.01]);
+        let dx = x - tunables.cave_radius * center_x as f32;
+        let dy = y - tunables.cave_radius * center_y as f32;
+        let (extent, extent_gradient) = if dx.abs() >= dy.abs() && dx.abs() >= -z
+        {
+            (dx.abs(), Vec3(1.0, 0.0, -tunables.cave_radius * 0.01 * center_x_gradient[0] as f32) * dx.signum())
+        } else if dy.abs() >= -z
+        {
+            (dy.abs(), Vec3(0.0, 1.0, -tunables.cave_radius * 0.01 * center_y_gradient[1] as f32) * dy.signum())
+        } else { (-z, Vec3(0.0, 0.0, -1.0)) };
+        let radius = tunables.cave_radius * blend.mix(|biome| biome.radius);
+        let radius_gradient = Vec3(0.0, 0.0, tunables.cave_radius * blend.mix_slope(|biome| biome.radius));
+        let border = (extent / radius).powi(tunables.cave_gen_border_power) * tunables.cave_gen_border_strength;
+        let border_gradient = (extent_gradient - radius_gradient * (extent / radius)) * (tunables.cave_gen_border_power as f32 * (extent / radius).powi(tunables.cave_gen_border_power - 1) / radius * tunables.cave_gen_border_strength);
+        //spawn
+        let (sx, sy) = (x - self.x0, y - self.y0);
+        let spawn = tunables.cave_gen_spawn_strength * (tunables.cave_gen_spawn_decay_rate * (sx * sx + sy * sy + z * z)).exp();
+        let spawn_gradient = Vec3(sx, sy, z) * (2.0 * tunables.cave_gen_spawn_decay_rate * spawn);
+
+        let value = noise + border - spawn + self.bias + blend.mix(|biome| biome.bias);
+        let gradient = noise_gradient + border_gradient - spawn_gradient + Vec3(0.0, 0.0, blend.mix_slope(|biome| biome.bias));
+        (value, gradient)
+    }
 }
 
-impl<T: noise::NoiseFn<[f64; 3]>> mold::Mold for Cave<T>
+impl<T: NoiseGradient> mold::Mold for Cave<T>
 {
-    fn value(&self, Vec3(x, y, z): Vec3) -> f32
+    fn value(&self, pos: Vec3) -> f32
     {
-        self.fun.get([x as f64, y as f64, z as f64]) as f32
-      + ((x - consts::CAVE_RADIUS * self.perlin.get([z as f64 * 0.01, 0.0]) as f32).abs().max((y - consts::CAVE_RADIUS * self.perlin.get([0.0, z as f64 * 0.01]) as f32).abs()).max(-z) / consts::CAVE_RADIUS).powi(consts::CAVE_GEN_BORDER_POWER) * consts::CAVE_GEN_BORDER_STRENGTH
-      - consts::CAVE_GEN_SPAWN_STRENGTH * (consts::CAVE_GEN_SPAWN_DECAY_RATE * ((x - self.x0)*(x - self.x0) + (y - self.y0)*(y - self.y0) + z*z)).exp()
-      + self.bias
+        match &self.features
+        {
+            //the blend does not depend on the gradients going in
+            Some(features) => features.apply((self.base_value(pos), Vec3(0.0, 0.0, 0.0)), pos).0,
+            None => self.base_value(pos)
+        }
     }
 
-    fn gradient(&self, Vec3(x, y, z): Vec3) -> Vec3
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.value_gradient(pos).1 }
+
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3)
     {
-        Vec3
-        (
-            (self.value(Vec3(x + consts::CAVE_GEN_GRADIENT_EPSILON, y, z)) - self.value(Vec3(x - consts::CAVE_GEN_GRADIENT_EPSILON, y, z))) / consts::CAVE_GEN_GRADIENT_EPSILON_2,
-            (self.value(Vec3(x, y + consts::CAVE_GEN_GRADIENT_EPSILON, z)) - self.value(Vec3(x, y - consts::CAVE_GEN_GRADIENT_EPSILON, z))) / consts::CAVE_GEN_GRADIENT_EPSILON_2,
-            (self.value(Vec3(x, y, z + consts::CAVE_GEN_GRADIENT_EPSILON)) - self.value(Vec3(x, y, z - consts::CAVE_GEN_GRADIENT_EPSILON))) / consts::CAVE_GEN_GRADIENT_EPSILON_2
-        )
+        let cave = self.base_value_gradient(pos);
+        self.features.as_ref().map_or(cave, |features| features.apply(cave, pos))
     }
 
+    //somewhere in the biome's palette, picked by a finer copy of its noise
     fn color(&self, pos: Vec3) -> Vec3
     {
-        Vec3(0.6, 0.5, 0.2) * (self.fun.get([2.0 * pos.0 as f64, 2.0 * pos.1 as f64, 2.0 * pos.2 as f64]) as f32 + 1.0)
+        let blend = biome::Blend::at(pos.2);
+        let color = |index: usize|
+        {
+            let [low, high] = biome::BIOMES[index].palette.map(Vec3::from);
+            let t = (self.funs[index].get([2.0 * pos.0 as f64, 2.0 * pos.1 as f64, 2.0 * pos.2 as f64]) as f32 + 1.0) / 2.0;
+            low + (high - low) * t
+        };
+        if blend.weight > 0.0 { color(blend.from) * (1.0 - blend.weight) + color(blend.to) * blend.weight } else { color(blend.from) }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn points() -> impl Iterator<Item = Vec3>
+    {
+        (0..2000).map(|i| Vec3((i as f32 * 0.731).sin() * 20.0, (i as f32 * 1.37).cos() * 20.0, i as f32 * 0.7 - 30.0))
+    }
+
+    #[test]
+    fn closest_request_first()
+    {
+        let mut requests = Requests { pending: HashMap::new(), queue: BinaryHeap::new(), cam_block: 0, closed: false };
+        for z in [5, -2, 1, 3] { requests.push(z, 0); }
+        //asked again in more detail, and given up on
+        requests.push(3, 1);
+        requests.pending.remove(&-2);
+        assert_eq!(requests.pop(), Some((1, 0)));
+        requests.focus(6.2);
+        requests.push(3, 2);
+        //still the same block, the queue with its stale entry is kept as it is
+        requests.focus(5.6);
+        assert_eq!(requests.queue.len(), 3);
+        assert_eq!(requests.pop(), Some((5, 0)));
+        assert_eq!(requests.pop(), Some((3, 2)));
+        assert_eq!(requests.pop(), None);
+    }
+
+    //the same seed and block always give the same pickups, lying in open space just above a floor
+    #[test]
+    fn pickup_placement()
+    {
+        tunables::init_default();
+        let cave = crate::config::Config::new().mold();
+        let probabilities = [1.0; pickup::KINDS.len()];
+        let layout = |seed: u32, z: i32| place_pickups(&cave, seed, z, &probabilities).iter().map(|pickup| (pickup.kind, pickup.pos.0, pickup.pos.1, pickup.pos.2, pickup.color.0)).collect::<Vec<_>>();
+        let mut placed = 0;
+        for z in 0..20
+        {
+            let pickups = layout(1, z);
+            assert_eq!(pickups, layout(1, z));
+            assert_ne!(pickups, layout(2, z));
+            for &(_, x, y, z, _) in &pickups
+            {
+                let pos = Vec3(x, y, z);
+                assert!(mold::Mold::value(&cave, pos) < 0.0);
+                let floor = collision::ray_cast(&cave, pos, Vec3(0.0, 1.0, 0.0), 1.0).unwrap();
+                assert!((floor.distance - consts::FLASH_FLOOR_HEIGHT).abs() < 0.01, "{}", floor.distance);
+            }
+            placed += pickups.len();
+        }
+        assert!(placed > 20, "only {placed} pickups placed");
+    }
+
+    fn fbm() -> CaveNoise { CaveNoise::Fractal(perlin::Fractal::new(perlin::FractalKind::Fbm, 3, 4, 0.07, 1.7, 0.8)) }
+
+    //the value only path has to agree with the gradient one to the bit, or meshes would not match the collision
+    #[test]
+    fn value_matches_gradient_path()
+    {
+        let noises = [fbm(), CaveNoise::Fractal(perlin::Fractal::new(perlin::FractalKind::Billow, 3, 4, 0.07, 1.7, 0.8)), CaveNoise::Perlin(perlin::Perlin::new(3), 0.1)];
+        for noise in &noises
+        {
+            for Vec3(x, y, z) in points()
+            {
+                let point = [x as f64, y as f64, z as f64];
+                assert_eq!(noise.get(point).to_bits(), noise.get_gradient(point).0.to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn noise_gradient()
+    {
+        for noise in [fbm(), CaveNoise::Perlin(perlin::Perlin::new(3), 0.1)]
+        {
+            for Vec3(x, y, z) in points()
+            {
+                let point = [x as f64, y as f64, z as f64];
+                //perlin clamps its value to -1..1, the gradient has a kink there
+                if noise.get(point).abs() > 0.9 { continue; }
+                let (analytic, numeric) = (noise.get_gradient(point).1, central_differences(&noise, point).1);
+                for axis in 0..3 { assert!((analytic[axis] - numeric[axis]).abs() < 0.005, "{point:?}: {analytic:?} vs {numeric:?}"); }
+            }
+        }
+    }
+
+    #[test]
+    fn cave_gradient()
+    {
+        tunables::init_default();
+        let cave = Cave::new(biome::BIOMES.iter().map(|_| fbm()).collect(), perlin::Perlin::new(3), 0.0);
+        let epsilon = 0.001;
+        for pos in points()
+        {
+            let (value, gradient) = mold::Mold::value_gradient(&cave, pos);
+            assert_eq!(value.to_bits(), mold::Mold::value(&cave, pos).to_bits());
+            let numeric = |axis: Vec3| (mold::Mold::value(&cave, pos + axis * epsilon) - mold::Mold::value(&cave, pos - axis * epsilon)) / (2.0 * epsilon);
+            let numeric = Vec3(numeric(Vec3(1.0, 0.0, 0.0)), numeric(Vec3(0.0, 1.0, 0.0)), numeric(Vec3(0.0, 0.0, 1.0)));
+            assert!((numeric - gradient).norm() < 0.02 * (1.0 + gradient.norm()), "{pos:?}: {gradient:?} vs {numeric:?}");
+        }
     }
 }
@@ -0,0 +1,280 @@
+use gru_misc::math::*;
+use crate::{mold::Mold, consts};
+
+//gravity points along +y
+const UP: Vec3 = Vec3(0.0, -1.0, 0.0);
+
+//first order distance estimate to the surface of a mold and the surface normal pointing into open space
+pub fn distance(mold: &impl Mold, pos: Vec3) -> (f32, Vec3)
+{
+    let (value, gradient) = mold.value_gradient(pos);
+    let norm = gradient.norm();
+    if norm == 0.0 { return (-value.signum() * f32::MAX, UP); }
+    (-value / norm, gradient * (-1.0 / norm))
+}
+
+//follows the gradient onto the surface, which for molds that grow like a distance is the closest point,
+//returns the point and the normal there, None if no surface is in reach
+pub fn closest_surface(mold: &impl Mold, pos: Vec3) -> Option<(Vec3, Vec3)>
+{
+    let mut point = pos;
+    for _ in 0..consts::SURFACE_SEARCH_STEPS
+    {
+        let (value, gradient) = mold.value_gradient(point);
+        let norm_sq = Vec3::dot(gradient, gradient);
+        if norm_sq == 0.0 { return None; }
+        let step = gradient * (-value / norm_sq);
+        point = point + step;
+        if step.norm() < consts::SURFACE_SEARCH_EPSILON { return Some((point, gradient * (-1.0 / norm_sq.sqrt()))); }
+    }
+    None
+}
+
+//whether any of the ball is inside the mold, errs towards overlapping where the distance estimate falls short
+pub fn sphere_overlaps(mold: &impl Mold, center: Vec3, radius: f32) -> bool
+{
+    distance(mold, center).0 < radius
+}
+
+pub struct RayHit
+{
+    pub distance: f32,
+    pub pos: Vec3,
+    //pointing into open space
+    pub normal: Vec3
+}
+
+//sphere traces from origin along dir, a ray starting inside the mold hits right away
+pub fn ray_cast(mold: &impl Mold, origin: Vec3, dir: Vec3, max_distance: f32) -> Option<RayHit>
+{
+    let dir = dir.unit();
+    let (mut last_free, mut t) = (0.0, 0.0);
+    for _ in 0..consts::RAY_MAX_STEPS
+    {
+        let (distance, normal) = distance(mold, origin + dir * t);
+        if distance < 0.0
+        {
+            if t == 0.0 { return Some(RayHit { distance: 0.0, pos: origin, normal }); }
+            //the surface lies between the last free point and this one
+            let (mut free, mut blocked) = (last_free, t);
+            for _ in 0..consts::COLLISION_REFINE_STEPS
+            {
+                let mid = 0.5 * (free + blocked);
+                if mold.value(origin + dir * mid) >= 0.0 { blocked = mid; } else { free = mid; }
+            }
+            let pos = origin + dir * blocked;
+            return Some(RayHit { distance: blocked, pos, normal: self::distance(mold, pos).1 });
+        }
+        last_free = t;
+        if t >= max_distance { return None; }
+        //the estimate is only first order, so steps stay short where the field bends
+        t = (t + distance.clamp(consts::COLLISION_MIN_STEP, consts::RAY_MAX_STEP)).min(max_distance);
+    }
+    None
+}
+
+//vertical capsule around a center point
+#[derive(Clone, Copy)]
+pub struct Capsule
+{
+    pub radius: f32,
+    pub half_length: f32
+}
+
+impl Capsule
+{
+    //at most radius apart, so the surface of the spheres dips in by less than 0.14 radius between two of them
+    fn spacing(&self) -> f32 { 2.0 * self.half_length / (2.0 * self.half_length / self.radius).ceil().max(1.0) }
+
+    //spheres along the axis
+    fn centers(&self, center: Vec3) -> impl Iterator<Item = Vec3>
+    {
+        let samples = (2.0 * self.half_length / self.radius).ceil().max(1.0) as usize + 1;
+        let half_length = self.half_length;
+        (0..samples).map(move |i| center + Vec3(0.0, (2.0 * i as f32 / (samples - 1) as f32 - 1.0) * half_length, 0.0))
+    }
+
+    //to the surface of the spheres, slightly short of the capsule's between them
+    pub fn distance(&self, mold: &impl Mold, center: Vec3) -> (f32, Vec3)
+    {
+        let mut nearest = (f32::MAX, UP);
+        for sphere in self.centers(center)
+        {
+            let (distance, normal) = distance(mold, sphere);
+            if distance - self.radius < nearest.0 { nearest = (distance - self.radius, normal); }
+        }
+        nearest
+    }
+
+    //the spheres grown until they reach the capsule's surface between them as well, so this errs towards overlapping
+    pub fn overlaps(&self, mold: &impl Mold, center: Vec3) -> bool
+    {
+        let radius = (self.radius * self.radius + 0.25 * self.spacing() * self.spacing()).sqrt();
+        self.centers(center).any(|sphere| sphere_overlaps(mold, sphere, radius))
+    }
+}
+
+pub struct Hit
+{
+    pub fraction: f32,
+    pub pos: Vec3,
+    pub normal: Vec3
+}
+
+//sphere traces the capsule from `from` along `motion` and reports the first contact
+pub fn sweep(mold: &impl Mold, capsule: &Capsule, from: Vec3, motion: Vec3) -> Option<Hit>
+{
+    let length = motion.norm();
+    if length == 0.0 { return None; }
+    let dir = motion * (1.0 / length);
+    let (mut last_free, mut t) = (0.0, 0.0);
+    for _ in 0..consts::COLLISION_MAX_STEPS
+    {
+        let (distance, normal) = capsule.distance(mold, from + dir * t);
+        if distance >= 0.0 { last_free = t; }
+        else if t > 0.0
+        {
+            //the last step went too far, find the contact between the last free and this point
+            let (mut free, mut blocked, mut normal) = (last_free, t, normal);
+            for _ in 0..consts::COLLISION_REFINE_STEPS
+            {
+                let mid = 0.5 * (free + blocked);
+                let (distance, mid_normal) = capsule.distance(mold, from + dir * mid);
+                if distance < 0.0 { blocked = mid; normal = mid_normal; } else { free = mid; }
+            }
+            return Some(Hit { fraction: free / length, pos: from + dir * free, normal });
+        }
+        else if Vec3::dot(normal, dir) < 0.0 { return Some(Hit { fraction: 0.0, pos: from, normal }); }
+        if t >= length { return None; }
+        t = (t + distance.clamp(consts::COLLISION_MIN_STEP, capsule.radius)).min(length);
+    }
+    //out of steps, stay at the last safe point rather than risk tunneling
+    Some(Hit { fraction: last_free / length, pos: from + dir * last_free, normal: capsule.distance(mold, from + dir * last_free).1 })
+}
+
+pub struct Slide
+{
+    pub pos: Vec3,
+    pub vel: Vec3,
+    pub contact: bool,
+    pub grounded: bool
+}
+
+//moves the capsule by vel * dt, sliding along every surface it touches
+pub fn move_and_slide(mold: &impl Mold, capsule: &Capsule, mut pos: Vec3, mut vel: Vec3, dt: f32) -> Slide
+{
+    let mut contact = false;
+    let mut grounded = false;
+    let mut touch = |normal: Vec3|
+    {
+        contact = true;
+        grounded = grounded || Vec3::dot(normal, UP) > consts::GROUND_SLOPE;
+    };
+    //push out of the surface if we start inside
+    for _ in 0..consts::COLLISION_MAX_SLIDES
+    {
+        let (distance, normal) = capsule.distance(mold, pos);
+        if distance >= 0.0 { break; }
+        pos = pos + normal * (consts::COLLISION_SKIN - distance);
+        vel = vel - normal * Vec3::dot(vel, normal).min(0.0);
+        touch(normal);
+    }
+    let mut motion = vel * dt;
+    for _ in 0..consts::COLLISION_MAX_SLIDES
+    {
+        match sweep(mold, capsule, pos, motion)
+        {
+            None =>
+            {
+                pos = pos + motion;
+                break;
+            },
+            Some(hit) =>
+            {
+                pos = hit.pos;
+                motion = motion * (1.0 - hit.fraction);
+                motion = motion - hit.normal * Vec3::dot(motion, hit.normal).min(0.0);
+                vel = vel - hit.normal * Vec3::dot(vel, hit.normal).min(0.0);
+                touch(hit.normal);
+            }
+        }
+    }
+    Slide { pos, vel, contact, grounded }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{mold::Sphere, flash::FlashMold};
+
+    fn close(a: Vec3, b: Vec3) -> bool { (a - b).norm() < 1e-3 }
+
+    #[test]
+    fn ray_hits()
+    {
+        //the side of the flash cylinder with radius 0.1
+        let hit = ray_cast(&FlashMold, Vec3(0.1, 0.0, 5.0), Vec3(0.0, 0.0, -2.0), 10.0).unwrap();
+        assert!((hit.distance - 4.9).abs() < 1e-3, "{}", hit.distance);
+        assert!(close(hit.pos, Vec3(0.1, 0.0, 0.1)) && close(hit.normal, Vec3(0.0, 0.0, 1.0)), "{:?} {:?}", hit.pos, hit.normal);
+        //its cap
+        let hit = ray_cast(&FlashMold, Vec3(3.0, 0.02, 0.0), Vec3(-1.0, 0.0, 0.0), 10.0).unwrap();
+        assert!(close(hit.pos, Vec3(consts::FLASH_HEIGHT, 0.02, 0.0)) && close(hit.normal, Vec3(1.0, 0.0, 0.0)), "{:?} {:?}", hit.pos, hit.normal);
+        let hit = ray_cast(&Sphere(1.0), Vec3(0.0, -4.0, 0.0), Vec3(0.0, 1.0, 0.0), 10.0).unwrap();
+        assert!((hit.distance - 3.0).abs() < 1e-3 && close(hit.normal, UP), "{} {:?}", hit.distance, hit.normal);
+    }
+
+    #[test]
+    fn ray_misses()
+    {
+        //the surface lies beyond max_distance
+        assert!(ray_cast(&FlashMold, Vec3(0.1, 0.0, 5.0), Vec3(0.0, 0.0, -1.0), 4.0).is_none());
+        assert!(ray_cast(&Sphere(1.0), Vec3(0.0, -4.0, 0.0), Vec3(0.0, 1.0, 0.0), 2.9).is_none());
+        //passing by
+        assert!(ray_cast(&FlashMold, Vec3(0.1, 0.5, 5.0), Vec3(0.0, 0.0, -1.0), 10.0).is_none());
+    }
+
+    #[test]
+    fn ray_inside()
+    {
+        let hit = ray_cast(&Sphere(1.0), Vec3(0.2, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), 1.0).unwrap();
+        assert_eq!(hit.distance, 0.0);
+        assert!(close(hit.pos, Vec3(0.2, 0.0, 0.0)) && close(hit.normal, Vec3(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn closest_point()
+    {
+        let (point, normal) = closest_surface(&Sphere(1.0), Vec3(0.0, 3.0, 0.0)).unwrap();
+        assert!(close(point, Vec3(0.0, 1.0, 0.0)) && close(normal, Vec3(0.0, 1.0, 0.0)), "{point:?} {normal:?}");
+        let (point, normal) = closest_surface(&Sphere(2.0), Vec3(0.5, 0.0, 0.0)).unwrap();
+        assert!(close(point, Vec3(2.0, 0.0, 0.0)) && close(normal, Vec3(1.0, 0.0, 0.0)), "{point:?} {normal:?}");
+        let (point, _) = closest_surface(&FlashMold, Vec3(0.0, 0.0, 2.0)).unwrap();
+        assert!(close(point, Vec3(0.0, 0.0, 0.1)), "{point:?}");
+    }
+
+    #[test]
+    fn overlaps()
+    {
+        let sphere = Sphere(1.0);
+        assert!(sphere_overlaps(&sphere, Vec3(0.0, 1.9, 0.0), 1.0));
+        assert!(!sphere_overlaps(&sphere, Vec3(0.0, 2.1, 0.0), 1.0));
+        assert!(sphere_overlaps(&sphere, Vec3(0.0, 0.0, 0.0), 0.01));
+        //the flash field is quadratic across the cylinder, its estimate stays short of the true distance
+        assert!(sphere_overlaps(&FlashMold, Vec3(0.0, 0.0, 0.25), 0.2));
+        assert!(!sphere_overlaps(&FlashMold, Vec3(0.0, 0.0, 1.0), 0.2));
+
+        let capsule = Capsule { radius: 0.2, half_length: 1.0 };
+        assert!(capsule.overlaps(&sphere, Vec3(1.1, 0.0, 0.0)));
+        assert!(!capsule.overlaps(&sphere, Vec3(1.3, 0.0, 0.0)));
+        //touching it between two of its spheres
+        let pebble = |pos: Vec3| crate::mold::Translate { mold: Sphere(0.05), offset: pos };
+        assert!(capsule.overlaps(&pebble(Vec3(0.24, -0.9, 0.0)), Vec3(0.0, 0.0, 0.0)));
+        assert!(!capsule.overlaps(&pebble(Vec3(0.3, -0.9, 0.0)), Vec3(0.0, 0.0, 0.0)));
+        //the ends of the capsule
+        assert!(capsule.overlaps(&sphere, Vec3(0.0, 2.1, 0.0)));
+        assert!(!capsule.overlaps(&sphere, Vec3(0.0, 2.3, 0.0)));
+        assert!(capsule.overlaps(&sphere, Vec3(0.0, -2.1, 0.0)));
+        assert!(!capsule.overlaps(&sphere, Vec3(0.0, -2.3, 0.0)));
+    }
+}
@@ -0,0 +1,263 @@
+use noise::{Seedable, MultiFractal};
+use crate::{cave, perlin, pickup, tunables, consts, biome};
+
+const CONFIG_PATH: &str = "cave.cfg";
+const CACHE_DIR: &str = "cache";
+const BINDINGS_PATH: &str = "bindings.cfg";
+const FRAMES_DIR: &str = "frames";
+const SCREENSHOTS_DIR: &str = "screenshots";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NoiseKind
+{
+    Billow,
+    Fbm,
+    HybridMulti,
+    Value,
+    OpenSimplex,
+    Perlin,
+    SuperSimplex
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fullscreen
+{
+    Off,
+    Borderless,
+    Exclusive
+}
+
+impl Fullscreen
+{
+    pub const ALL: [Self; 3] = [Self::Off, Self::Borderless, Self::Exclusive];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Self::Off => "off",
+            Self::Borderless => "borderless",
+            Self::Exclusive => "exclusive"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> { Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(name)) }
+}
+
+impl NoiseKind
+{
+    pub const ALL: [Self; 7] = [Self::Billow, Self::Fbm, Self::HybridMulti, Self::Value, Self::OpenSimplex, Self::Perlin, Self::SuperSimplex];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Self::Billow => "billow",
+            Self::Fbm => "fbm",
+            Self::HybridMulti => "hybridmulti",
+            Self::Value => "value",
+            Self::OpenSimplex => "opensimplex",
+            Self::Perlin => "perlin",
+            Self::SuperSimplex => "supersimplex"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> { Self::ALL.into_iter().find(|kind| kind.name().eq_ignore_ascii_case(name)) }
+}
+
+pub fn random_seed() -> u32 { std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH).unwrap().as_nanos() as u32 }
+
+//everything needed to reproduce a cave exactly
+#[derive(Clone)]
+pub struct Config
+{
+    pub seed: u32,
+    pub noise: NoiseKind,
+    pub octaves: usize,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub persistence: f64,
+    pub probabilities: [f32; pickup::KINDS.len()],
+    pub tunables: tunables::Tunables,
+    pub tick_rate: f32,
+    pub cache: Option<String>,
+    pub bindings: String,
+    pub fullscreen: Fullscreen,
+    pub screenshots: String,
+    //supersampled screenshots are this many times the window size
+    pub screenshot_scale: u32,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    //number of frames to render offscreen instead of opening a window
+    pub headless: Option<u32>,
+    pub frames: String,
+    pub frame_size: (u32, u32),
+    pub frame_rate: f32
+}
+
+impl Config
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            seed: 0,
+            noise: NoiseKind::Billow,
+            octaves: consts::CAVE_GEN_OCTAVES,
+            frequency: consts::CAVE_GEN_FREQUENCY,
+            lacunarity: consts::CAVE_GEN_LUCUNARITY,
+            persistence: consts::CAVE_GEN_PERSISTANCE,
+            probabilities: pickup::probabilities(),
+            tunables: tunables::Tunables::new(),
+            tick_rate: consts::TICK_RATE,
+            cache: Some(CACHE_DIR.to_owned()),
+            bindings: BINDINGS_PATH.to_owned(),
+            fullscreen: Fullscreen::Off,
+            screenshots: SCREENSHOTS_DIR.to_owned(),
+            screenshot_scale: consts::SCREENSHOT_SCALE,
+            record: None,
+            replay: None,
+            headless: None,
+            frames: FRAMES_DIR.to_owned(),
+            frame_size: consts::HEADLESS_SIZE,
+            frame_rate: consts::HEADLESS_FRAME_RATE
+        }
+    }
+
+    //defaults < config file (--config <path> or ./cave.cfg) < command line (--<key> <value>)
+    pub fn load() -> Self
+    {
+        let mut config = Self::new();
+        let mut args: Vec<String> = std::env::args().skip(1).collect();
+        match args.iter().position(|arg| arg == "--config")
+        {
+            Some(i) =>
+            {
+                let path = args.get(i + 1).cloned().unwrap_or_else(|| panic!("--config needs a path"));
+                args.drain(i..=i + 1);
+                config.read_file(&path);
+            },
+            None => if std::path::Path::new(CONFIG_PATH).exists() { config.read_file(CONFIG_PATH); }
+        }
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next()
+        {
+            let key = arg.strip_prefix("--").unwrap_or_else(|| panic!("unexpected argument \"{arg}\""));
+            let value = args.next().unwrap_or_else(|| panic!("--{key} needs a value"));
+            config.set(key, &value).unwrap_or_else(|err| panic!("--{key}: {err}"));
+        }
+        config.tunables.check().unwrap_or_else(|err| panic!("{err}"));
+        config
+    }
+
+    fn read_file(&mut self, path: &str)
+    {
+        let text = std::fs::read_to_string(path).unwrap_or_else(|err| panic!("{path}: {err}"));
+        for (number, line) in text.lines().enumerate()
+        {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() { continue; }
+            let (key, value) = line.split_once('=').unwrap_or_else(|| panic!("{path}:{}: expected \"key = value\"", number + 1));
+            self.set(key.trim(), value.trim()).unwrap_or_else(|err| panic!("{path}:{}: {err}", number + 1));
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String>
+    {
+        fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> { value.parse().map_err(|_| format!("invalid value \"{value}\"")) }
+        match key
+        {
+            "seed" => self.seed = if value == "random" { random_seed() } else { parse(value)? },
+            "noise" => self.noise = NoiseKind::from_name(value).ok_or_else(||
+            {
+                let names: Vec<_> = NoiseKind::ALL.iter().map(|kind| kind.name()).collect();
+                format!("unknown noise \"{value}\", expected one of {}", names.join(", "))
+            })?,
+            "octaves" => self.octaves = parse(value)?,
+            "frequency" => self.frequency = parse(value)?,
+            "lacunarity" => self.lacunarity = parse(value)?,
+            "persistence" => self.persistence = parse(value)?,
+            "tick_rate" => self.tick_rate = match parse(value)?
+            {
+                rate if rate > 0.0 => rate,
+                _ => return Err(format!("tick rate must be positive, got \"{value}\""))
+            },
+            "cache" => self.cache = if value == "off" { None } else { Some(value.to_owned()) },
+            "bindings" => self.bindings = value.to_owned(),
+            "fullscreen" => self.fullscreen = Fullscreen::from_name(value).ok_or_else(||
+            {
+                let names: Vec<_> = Fullscreen::ALL.iter().map(|mode| mode.name()).collect();
+                format!("unknown fullscreen mode \"{value}\", expected one of {}", names.join(", "))
+            })?,
+            "screenshots" => self.screenshots = value.to_owned(),
+            "screenshot_scale" => self.screenshot_scale = match parse(value)?
+            {
+                scale if scale > 0 => scale,
+                _ => return Err(format!("screenshot scale must be positive, got \"{value}\""))
+            },
+            "record" => self.record = Some(value.to_owned()),
+            "replay" => self.replay = Some(value.to_owned()),
+            "headless" => self.headless = Some(parse(value)?),
+            "frames" => self.frames = value.to_owned(),
+            "frame_size" => self.frame_size = value.split_once('x').and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                .filter(|&(width, height)| width > 0 && height > 0)
+                .ok_or_else(|| format!("expected \"<width>x<height>\", got \"{value}\""))?,
+            "frame_rate" => self.frame_rate = match parse(value)?
+            {
+                rate if rate > 0.0 => rate,
+                _ => return Err(format!("frame rate must be positive, got \"{value}\""))
+            },
+            _ => match key.strip_suffix("_probability").and_then(pickup::from_name)
+            {
+                Some(kind) => self.probabilities[kind] = parse(value)?,
+                None => self.tunables.set(key, value)?
+            }
+        }
+        Ok(())
+    }
+
+    pub fn mold(&self) -> cave::Cave<cave::CaveNoise>
+    {
+        //the first biome keeps the configured noise as it is
+        let funs = biome::BIOMES.iter().enumerate().map(|(index, biome)| self.noise(self.seed.wrapping_add(index as u32), self.frequency * biome.frequency, self.persistence * biome.persistence)).collect();
+        cave::Cave::new(funs, perlin::Perlin::new(self.seed), self.tunables.cave_gen_bias)
+    }
+
+    fn noise(&self, seed: u32, frequency: f64, persistence: f64) -> cave::CaveNoise
+    {
+        match self.noise
+        {
+            NoiseKind::Billow => cave::CaveNoise::Fractal(perlin::Fractal::new(perlin::FractalKind::Billow, seed, self.octaves, frequency, self.lacunarity, persistence)),
+            NoiseKind::Fbm => cave::CaveNoise::Fractal(perlin::Fractal::new(perlin::FractalKind::Fbm, seed, self.octaves, frequency, self.lacunarity, persistence)),
+            NoiseKind::HybridMulti => cave::CaveNoise::HybridMulti(noise::HybridMulti::new().set_seed(seed)
+                .set_octaves(self.octaves).set_frequency(frequency).set_lacunarity(self.lacunarity).set_persistence(persistence)),
+            NoiseKind::Value => cave::CaveNoise::Value(noise::Value::new().set_seed(seed), frequency),
+            NoiseKind::OpenSimplex => cave::CaveNoise::OpenSimplex(noise::OpenSimplex::new().set_seed(seed), frequency),
+            NoiseKind::Perlin => cave::CaveNoise::Perlin(perlin::Perlin::new(seed), frequency),
+            NoiseKind::SuperSimplex => cave::CaveNoise::SuperSimplex(noise::SuperSimplex::new().set_seed(seed), frequency)
+        }
+    }
+}
+
+//prints the command line that reproduces this cave
+impl std::fmt::Display for Config
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "--seed {} --noise {}", self.seed, self.noise.name())?;
+        match self.noise
+        {
+            NoiseKind::Billow | NoiseKind::Fbm | NoiseKind::HybridMulti => write!(f, " --octaves {} --frequency {} --lacunarity {} --persistence {}", self.octaves, self.frequency, self.lacunarity, self.persistence),
+            _ => write!(f, " --frequency {}", self.frequency)
+        }?;
+        for ((kind, probability), default) in pickup::KINDS.iter().zip(self.probabilities).zip(pickup::probabilities())
+        {
+            if probability != default { write!(f, " --{}_probability {probability}", kind.name)?; }
+        }
+        for ((key, value), (_, default)) in self.tunables.entries().into_iter().zip(tunables::Tunables::new().entries())
+        {
+            if value != default { write!(f, " --{key} {value}")?; }
+        }
+        Ok(())
+    }
+}
@@ -1,17 +1,13 @@
-/* bg.vert
-	- RADIUS = 2 * CAVE_RADIUS
-	- BACK_DISTANCE = BLOCK_LENGTH * (BLOCK_DESPAWN_BACK_DISTANCE + 1)
-	- FRONT_DISTANCE = BLOCK_LENGTH * (BLOCK_SPAWN_FRONT_DISTANCE + 1)
-*/
-
-// from here
+//defaults of the tunables that can be overridden by the config file (see tunables.rs)
 pub const CAVE_RADIUS: f32 = 15.0;
 pub const BLOCK_LENGTH: f32 = 30.0;
 pub const BLOCK_SPAWN_FRONT_DISTANCE: i32 = 10;
 pub const BLOCK_DESPAWN_BACK_DISTANCE: i32 = 3;
-// to here relevant for bg.vert
 
 pub const CAVE_RESOLUTION: u32 = 80;
+pub const CAVE_LOD_DISTANCES: [f32; 2] = [2.5, 5.5];
+pub const CAVE_SKIRT_LENGTH: f32 = 2.0; //in cells of the block's own resolution
+pub const CAVE_SKIRT_EPSILON: f32 = 0.001;
 pub const CAVE_GEN_OCTAVES: usize = 2;
 pub const CAVE_GEN_FREQUENCY: f64 = 0.07;
 pub const CAVE_GEN_LUCUNARITY: f64 = 1.7;
@@ -23,9 +19,30 @@ pub const CAVE_GEN_SPAWN_STRENGTH: f32 = 4.0;
 pub const CAVE_GEN_SPAWN_DECAY_RATE: f32 = -3.0;
 pub const CAVE_GEN_GRADIENT_EPSILON: f32 = 0.05;
 pub const CAVE_GEN_GRADIENT_EPSILON_2: f32 = 2.0 * CAVE_GEN_GRADIENT_EPSILON;
+//features composed into the cave, each kind repeats every so many units along the tunnel, 0 turns it off
+pub const CAVE_CHAMBER_SPACING: f32 = 0.0;
+pub const CAVE_TUNNEL_SPACING: f32 = 0.0;
+pub const CAVE_PILLAR_SPACING: f32 = 0.0;
+pub const CAVE_STALACTITE_SPACING: f32 = 0.0;
+pub const CAVE_CHAMBER_RADIUS: f32 = 12.0;
+pub const CAVE_TUNNEL_RADIUS: f32 = 4.0;
+pub const CAVE_TUNNEL_ANGLE: f32 = std::f32::consts::FRAC_PI_6; //swept forward from straight sideways
+pub const CAVE_PILLAR_RADIUS: f32 = 1.5;
+pub const CAVE_PILLAR_OFFSET: f32 = 0.4; //off the tunnel center, in cave radii
+pub const CAVE_STALACTITE_LENGTH: f32 = 4.0;
+pub const CAVE_STALACTITE_RADIUS: f32 = 0.6;
+pub const CAVE_FEATURE_STRENGTH: f32 = 0.25; //noise units per unit of distance
+pub const CAVE_FEATURE_SMOOTHNESS: f32 = 0.5;
+//see simplify.rs, the area is twice the triangle's
+pub const MESH_WELD_EPSILON: f32 = 0.0001;
+pub const MESH_DEGENERATE_AREA: f32 = 0.000001;
+pub const MESH_SIMPLIFY_ERROR: f32 = 0.02;
+pub const MESH_SIMPLIFY_MIN_COS: f32 = 0.5; //of the angle a triangle may turn by in one collapse
+//see biome.rs
+pub const BIOME_LENGTH: f32 = 600.0;
+pub const BIOME_BLEND: f32 = 150.0;
 
 pub const CAM_NEAR: f32 = 0.01;
-pub const CAM_FAR: f32 = BLOCK_LENGTH * BLOCK_SPAWN_FRONT_DISTANCE as f32 * 1.5; //needs to be larger than bg.vert::FRONT_DISTANCE
 pub const CAM_ANGLE: f32 = std::f32::consts::FRAC_PI_4;
 pub const MOUSE_SENSITIVITY: f32 = 0.0001;
 
@@ -38,20 +55,54 @@ pub const LIGHT_COLOR: (f32, f32, f32) = (24.0, 14.0, 6.0); //(12.0, 7.0, 3.0);
 pub const LIGHT_ANGLE: f32 = -0.2;
 pub const LIGHT_ANGLE_INNER: f32 = std::f32::consts::TAU / 360.0 * 10.0;
 pub const LIGHT_ANGLE_OUTER: f32 = std::f32::consts::TAU / 360.0 * 15.0;
-pub const LIGHT_FREQUENCY: f64 = 0.6;
-pub const LIGHT_BIAS: f64 = 0.5;
+pub const BATTERY_DRAIN: f32 = 1.0 / 120.0;
+pub const BATTERY_LOW: f32 = 0.25;
+pub const BATTERY_MIN_BRIGHTNESS: f32 = 0.3;
+pub const BATTERY_FLICKER_FREQUENCY: f64 = 8.0;
+pub const BATTERY_COLOR: (f32, f32, f32) = (1.0, 0.9, 0.2);
+pub const BATTERY_HUD_DIGITS: usize = 3;
 pub const AMBIENT_LIGHT_COLOR: (f32, f32, f32) = (0.16, 0.16, 0.16); //(0.08, 0.08, 0.08); TODO revert to this when MSAA x SRGB bug fixed!!!
 
+pub const TICK_RATE: f32 = 120.0;
+pub const MAX_FRAME_TIME: f32 = 0.25;
+//size and rate of the frames rendered by --headless
+pub const HEADLESS_SIZE: (u32, u32) = (1280, 720);
+pub const HEADLESS_FRAME_RATE: f32 = 60.0;
+//captures being copied or written at once, screenshots beyond that are skipped
+pub const SCREENSHOT_QUEUE: usize = 4;
+pub const SCREENSHOT_SCALE: u32 = 2;
+//supersampled captures are scaled down to fit, every vulkan device supports images and framebuffers this large
+pub const MAX_CAPTURE_SIZE: u32 = 4096;
 pub const GRAV: f32 = 10.0;
-pub const COLLISION_FORCE: f32 = 400.0;
-pub const COLLISION_DRAG: f32 = 0.005;
 pub const SURFACE_DRAG: f32 = 0.08;
 pub const AIR_DRAG: f32 = 0.95;
+pub const COLLISION_SKIN: f32 = 0.01;
+pub const COLLISION_MIN_STEP: f32 = 0.05;
+pub const COLLISION_MAX_STEPS: usize = 64;
+pub const COLLISION_REFINE_STEPS: usize = 8;
+pub const COLLISION_MAX_SLIDES: usize = 4;
+pub const RAY_MAX_STEPS: usize = 256;
+pub const RAY_MAX_STEP: f32 = 1.0;
+pub const SURFACE_SEARCH_STEPS: usize = 16;
+pub const SURFACE_SEARCH_EPSILON: f32 = 0.001;
+pub const GROUND_SLOPE: f32 = 0.5;
 pub const ACCELERATION: f32 = 20.0;
 pub const MIN_ACCELERATION: f32 = 6.0;
 pub const JUMP: f32 = 5.5;
 pub const JUMP_COOLDOWN: f32 = 1.0;
 pub const CONTROL_TIME: f32 = 0.2;
+//a jump still counts as from the ground for this long after running over an edge
+pub const COYOTE_TIME: f32 = 0.1;
+pub const HOOK_RANGE: f32 = 25.0;
+pub const HOOK_PULL: f32 = 18.0;
+//the rope does not reel in any shorter, so the player hangs below the anchor instead of getting stuck on it
+pub const HOOK_MIN_LENGTH: f32 = 1.5;
+//the anchor is drawn as a small octahedron, the rope as a row of beads from the player's middle to it
+pub const HOOK_ANCHOR_SIZE: f32 = 0.15;
+pub const HOOK_ANCHOR_COLOR: (f32, f32, f32) = (0.9, 0.9, 0.95);
+pub const ROPE_BEAD_SIZE: f32 = 0.03;
+pub const ROPE_BEAD_SPACING: f32 = 0.1;
+pub const ROPE_COLOR: (f32, f32, f32) = (0.6, 0.45, 0.3);
 
 pub const FIGUR_HEIGHT: f32 = 0.9;
 pub const FIGUR_WIDTH: f32 = 0.25 * FIGUR_HEIGHT;
@@ -60,12 +111,27 @@ pub const EYE_HEIGHT: f32 = 7.0 / 8.0 * FIGUR_HEIGHT;
 pub const FLASH_POWER: f32 = 5.0;
 pub const FLASH_AMBIENT_POWER: f32 = 1.5;
 pub const FLASH_AMBIENT_DECAY: f32 = 0.05;
+pub const FLASH_BATTERY: f32 = 0.25;
 pub const FLASH_RADIUS: f32 = 0.1;
 pub const FLASH_RADIUS_SQ: f32 = FLASH_RADIUS * FLASH_RADIUS;
 pub const FLASH_HEIGHT: f32 = 0.3;
 pub const FLASH_BLOCK_PROB: f32 = 0.2;
+pub const FLASH_PLACEMENT_TRIES: u32 = 8;
+pub const FLASH_FLOOR_HEIGHT: f32 = 0.1;
 pub const FLASH_EPS: f32 = 0.05;
 pub const FLASH_RESOLUTION: u32 = 10;
 pub const PICKUP_RANGE: f32 = 3.0;
 
 pub const SCORE_DIGITS: usize = 3; //needs to be changed in main.rs under "compute score"
+pub const BEST_DIGITS: usize = 5; //needs to be changed in main.rs under "personal best"
+pub const BEST_OFFSET: f32 = 2.5;
+pub const TEXT_COLOR: (f32, f32, f32) = (0.0, 0.1, 0.8);
+pub const TEXT_HEIGHT: f32 = 0.1;
+pub const MENU_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ ";
+pub const MENU_MAX_CHARS: usize = 256;
+pub const MENU_TITLE_SCALE: f32 = 2.0;
+pub const MENU_LINE_SPACING: f32 = 1.5;
+pub const PICKUP_HUD_DIGITS: usize = 2;
+pub const PICKUP_HUD_BOX: f32 = 0.8;
+pub const PICKUP_HUD_OFFSET: f32 = 2.5;
+pub const LEADERBOARD_SIZE: usize = 10;
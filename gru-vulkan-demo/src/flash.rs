@@ -1,6 +1,6 @@
 use super::*;
 use crate::Vec3;
-use rand::distributions::{Distribution, Uniform};
+use rand::{SeedableRng, distributions::{Distribution, Uniform}};
 
 #[derive(VertexAttributeGroupReprCpacked)]
 #[repr(C, packed)]
@@ -24,22 +24,46 @@ pub struct FlashMold;
 
 impl mold::Mold for FlashMold
 {
-    fn value(&self, pos: Vec3) -> f32
+    fn value(&self, pos: Vec3) -> f32 { self.value_gradient(pos).0 }
+
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.value_gradient(pos).1 }
+
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3)
     {
-        let rad = consts::FLASH_RADIUS_SQ - pos.2 * pos.2 - pos.1 * pos.1;
-        let hei = consts::FLASH_HEIGHT - pos.0.abs();
-        if rad < 0.0 && hei < 0.0 { rad.max(hei) }
-        else if rad < 0.0 && hei > 0.0 { rad }
-        else if rad > 0.0 && hei < 0.0 { hei }
-        else { rad.min(hei) }
+        let rad = (consts::FLASH_RADIUS_SQ - pos.2 * pos.2 - pos.1 * pos.1, Vec3(0.0, -2.0 * pos.1, -2.0 * pos.2));
+        let hei = (consts::FLASH_HEIGHT - pos.0.abs(), Vec3(-pos.0.signum(), 0.0, 0.0));
+        //outside of both the larger one wins, otherwise the smaller one
+        let take_rad = if rad.0 < 0.0 && hei.0 < 0.0 { rad.0 > hei.0 } else { rad.0 < hei.0 };
+        if take_rad { rad } else { hei }
     }
-
-    fn gradient(&self, _: Vec3) -> Vec3 { Vec3(0.8, 0.1, 0.1) }
     
-    fn color(&self, _: Vec3) -> Vec3 
+    //a pure function of the position, so a placed flash always has the same color
+    fn color(&self, pos: Vec3) -> Vec3
     {
         let range = Uniform::from(0.0..1.0);
-        let mut rng = rand::thread_rng();
+        let bits = [pos.0, pos.1, pos.2].map(|x| x.to_bits() as u64);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(bits[0] ^ bits[1].rotate_left(21) ^ bits[2].rotate_left(42));
         Vec3(range.sample(&mut rng), range.sample(&mut rng), range.sample(&mut rng)).unit()
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use mold::Mold;
+
+    #[test]
+    fn color_is_pure()
+    {
+        let positions = [Vec3(1.0, 2.0, 3.0), Vec3(1.0, 2.0, 3.001), Vec3(-4.0, 0.5, 120.0)];
+        let color = |pos: Vec3| { let color = FlashMold.color(pos); [color.0, color.1, color.2] };
+        let colors = positions.map(color);
+        for (pos, expected) in positions.into_iter().zip(colors)
+        {
+            assert_eq!(color(pos), expected);
+            assert!((FlashMold.color(pos).norm() - 1.0).abs() < 1e-5);
+        }
+        assert!(colors[0] != colors[1] && colors[0] != colors[2]);
+    }
+}
@@ -3,13 +3,26 @@ mod cave;
 mod camera;
 mod consts;
 mod flash;
+mod sim;
+mod config;
+mod replay;
+mod scores;
+mod perlin;
+mod collision;
+mod cache;
+mod pickup;
+mod tunables;
+mod bindings;
+mod menu;
+mod screenshot;
+mod biome;
+mod simplify;
 
 use gru_vulkan::*;
 use gru_misc::{math::*, text_sdf::*, time::*, marching_cubes};
-use winit::{*, event::ElementState, keyboard::{PhysicalKey, KeyCode}};
-use noise::{self, NoiseFn, Seedable};
-use std::{sync::{mpsc, Arc, Mutex}, collections::hash_map};
-use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+use winit::{*, event::ElementState, keyboard::PhysicalKey};
+use std::sync::{mpsc, Arc, Mutex};
+use ahash::AHashMap as HashMap;
 use mold::Mold;
 
 const ATLAS_SIZE: u32 = 512;
@@ -28,6 +41,10 @@ const TEXT_FRAGMENT: Shader = frag_shader!("res/glsl/text.frag");
 pub struct CamBinding
 {
     mat: Mat4,
+    bg_radius: f32,
+    bg_back_distance: f32,
+    bg_front_distance: f32,
+    _padding0: f32
 }
 
 #[derive(Clone, Copy, DescriptorStructReprC)]
@@ -37,7 +54,7 @@ pub struct LightBinding
     z_bias: f32,
     cos_angle_inner: f32,
     cos_angle_outer: f32,
-    _padding0: f32,
+    battery: f32,
     ambient: (f32, f32, f32),
     _padding1: f32,
     flash_ambient: (f32, f32, f32),
@@ -46,7 +63,10 @@ pub struct LightBinding
     _padding3: f32,
     pos: (f32, f32, f32),
     _padding4: f32,
-    dir: (f32, f32, f32)
+    dir: (f32, f32, f32),
+    biome_length: f32,
+    biome_blend: f32,
+    biome_count: f32
 }
 
 #[derive(Clone, Copy, DescriptorStructReprC)]
@@ -66,7 +86,7 @@ pub struct Vertex
     #[location = 1]
     pub normal: F3,
     #[location = 2]
-    pub tex_coords: F2
+    pub color: F3
 }
 
 #[derive(VertexAttributeGroupReprCpacked)]
@@ -76,29 +96,147 @@ pub struct TextVertex
     #[location = 0]
     pub position: F2,
     #[location = 1]
-    pub tex_coords: F3
+    pub tex_coords: F3,
+    #[location = 2]
+    pub color: F3
+}
+
+fn fullscreen(window: &window::Window, mode: config::Fullscreen) -> Option<window::Fullscreen>
+{
+    match mode
+    {
+        config::Fullscreen::Off => None,
+        config::Fullscreen::Borderless => Some(window::Fullscreen::Borderless(None)),
+        //the biggest, then fastest mode the monitor offers
+        config::Fullscreen::Exclusive => window.current_monitor()
+            .and_then(|monitor| monitor.video_modes().max_by_key(|mode| (mode.size().width * mode.size().height, mode.refresh_rate_millihertz())))
+            .map(window::Fullscreen::Exclusive)
+    }
+}
+
+//the cursor is only captured while actually playing
+fn grab_cursor(window: &window::Window, grab: bool)
+{
+    window.set_cursor_grab(if grab { window::CursorGrabMode::Confined } else { window::CursorGrabMode::None }).unwrap();
+    window.set_cursor_visible(!grab);
+}
+
+//one line of text in text.vert units, either starting at offset or centered on it
+struct TextLine
+{
+    text: String,
+    offset: (f32, f32),
+    scale: f32,
+    color: (f32, f32, f32),
+    solid: bool,
+    centered: bool
+}
+
+fn layout_text(atlas: &Atlas, line: &TextLine, vertices: &mut Vec<TextVertex>, indices: &mut Vec<u16>)
+{
+    let base = vertices.len();
+    let mut quads = Vec::new();
+    atlas.text
+    (
+        &line.text,
+        Layout { width: std::f32::MAX, align: Align::Left, auto_wrap: false },
+        &mut |i| indices.push((base + i as usize) as u16),
+        &mut |(cx, cy, cl), p: (f32, f32)| quads.push(((cx, cy, cl as f32), p))
+    );
+    let center = if line.centered
+    {
+        let (min, max) = quads.iter().fold(((f32::MAX, f32::MAX), (f32::MIN, f32::MIN)), |(min, max), (_, p)| ((min.0.min(p.0), min.1.min(p.1)), (max.0.max(p.0), max.1.max(p.1))));
+        ((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0)
+    } else { (0.0, 0.0) };
+    vertices.extend(quads.into_iter().map(|(coords, p)| TextVertex
+    {
+        position: ((p.0 - center.0) * line.scale + line.offset.0, (p.1 - center.1) * line.scale + line.offset.1).into(),
+        //text.frag fills the quad when the layer is negative
+        tex_coords: if line.solid { (0.0, 0.0, -1.0).into() } else { coords.into() },
+        color: line.color.into()
+    }));
 }
 
+//prints the result of a run and ranks it if there is a leaderboard, replays don't get one
+fn finish_run<T: cave::NoiseGradient>(sim: &sim::Simulation<T>, recorder: Option<replay::Recorder>, leaderboard: Option<&mut scores::Leaderboard>) -> Option<usize>
+{
+    println!("Score {:.0}, distance {:.1}, time {:.2}, flashes {}", sim.score(), sim.cam.pos.2, sim.time, sim.flashes_collected);
+    if let Some(recorder) = recorder { println!("Replay saved to {}", recorder.finish()); }
+    let leaderboard = leaderboard?;
+    let rank = leaderboard.insert(scores::Entry
+    {
+        distance: sim.cam.pos.2,
+        score: sim.score(),
+        time: sim.time,
+        flashes: sim.flashes_collected,
+        seed: sim.seed,
+        date: scores::now()
+    });
+    leaderboard.save();
+    leaderboard.print(rank);
+    rank
+}
+
+fn print_stats(stats: cave::GeneratorStats)
+{
+    if stats.generated > 0
+    {
+        println!("Generated {} blocks in {:.1} ms on average, {:.1} ms at most, {} cancelled, up to {} queued",
+            stats.generated, stats.total_time.as_secs_f32() * 1000.0 / stats.generated as f32, stats.max_time.as_secs_f32() * 1000.0, stats.cancelled, stats.max_queued);
+    }
+    let simplify::Counts { vertices, indices } = stats.counts;
+    if vertices[0] > 0
+    {
+        println!("Cleaned meshes up from {} to {} vertices and {} to {} indices", vertices[0], vertices[1], indices[0], indices[1]);
+    }
+}
+
+//score, personal best, battery charge and a box with the remaining seconds for every running pickup
+fn hud_lines<T: cave::NoiseGradient>(sim: &sim::Simulation<T>, best_digits: Option<&str>) -> Vec<TextLine>
+{
+    let line = |text: String, offset: f32, color: (f32, f32, f32), solid: bool| TextLine { text, offset: (offset, 0.0), scale: 1.0, color, solid, centered: false };
+    let mut lines = vec![line(format!("{:03}", sim.score().round() as u32), 0.0, consts::TEXT_COLOR, false)];
+    if let Some(best_digits) = best_digits { lines.push(line(best_digits.to_owned(), consts::BEST_OFFSET, consts::TEXT_COLOR, false)); }
+    let mut offset = consts::BEST_OFFSET * lines.len() as f32;
+    lines.push(line("0".to_owned(), offset, consts::BATTERY_COLOR, true));
+    lines.push(line(format!("{:0width$}", (sim.battery * 100.0).ceil() as u32, width = consts::BATTERY_HUD_DIGITS), offset + consts::PICKUP_HUD_BOX, consts::BATTERY_COLOR, false));
+    offset += consts::BEST_OFFSET;
+    for (kind, remaining) in &sim.active
+    {
+        let color = pickup::KINDS[*kind].color.unwrap_or(consts::TEXT_COLOR);
+        lines.push(line("0".to_owned(), offset, color, true));
+        lines.push(line(format!("{:0width$}", remaining.ceil() as u32, width = consts::PICKUP_HUD_DIGITS), offset + consts::PICKUP_HUD_BOX, color, false));
+        offset += consts::PICKUP_HUD_OFFSET;
+    }
+    lines
+}
+
+fn best_digits(leaderboard: &scores::Leaderboard) -> Option<String> { leaderboard.best().map(|best| format!("{:05}", best.distance.max(0.0).round() as u32)) }
+
 fn main()
 {
-//window setup
-    let mut event_loop = event_loop::EventLoop::new().unwrap();
-    let window = window::WindowBuilder::new()
-        .with_title("gru_vulkan_demo: Cave Jumper")
-        .with_inner_size(dpi::PhysicalSize { width: 1024.0_f32, height: 768.0 })
-        .with_visible(false)
-        .with_resizable(false)
-        .build(&event_loop)
-        .unwrap();
-    window.set_cursor_grab(window::CursorGrabMode::Confined).unwrap();
-    window.set_cursor_visible(false);
-    //let monitor = window.available_monitors().nth(0).unwrap();
-    //let mode = monitor.video_modes().nth(0).unwrap();
-    //window.set_fullscreen(Some(window::Fullscreen::Exclusive(mode)));
-    //window.set_fullscreen(Some(window::Fullscreen::Borderless(None)));
-    let (width, height) = window.inner_size().into();
-//initialization, queue fetching and swapchain creation
-    let instance = Instance::new(Some(&window));
+//config, the tunables have to be known before anything is sized by them
+    let mut config = config::Config::load();
+    let mut player = config.replay.clone().map(|path| replay::Player::open(&path, &mut config).peekable());
+    println!("Cave: {config}");
+    tunables::init(config.tunables.clone());
+//window setup, headless runs go without window and event loop
+    let event_loop = config.headless.is_none().then(|| event_loop::EventLoop::new().unwrap());
+    let window = event_loop.as_ref().map(|event_loop|
+    {
+        let window = window::WindowBuilder::new()
+            .with_title("gru_vulkan_demo: Cave Jumper")
+            .with_inner_size(dpi::PhysicalSize { width: 1024.0_f32, height: 768.0 })
+            .with_visible(false)
+            .with_resizable(true)
+            .build(event_loop)
+            .unwrap();
+        window.set_fullscreen(fullscreen(&window, config.fullscreen));
+        window
+    });
+    let (mut width, mut height) = window.as_ref().map_or(config.frame_size, |window| window.inner_size().into());
+//initialization and queue fetching
+    let instance = Instance::new(window.as_ref());
     let physical_devices = instance.physical_devices();
     let gpu = &physical_devices[0];
     let graphic_queue_family_info = &gpu.queue_families()[0];
@@ -106,18 +244,26 @@ fn main()
     let graphic_queue_family = device.get_queue_family(graphic_queue_family_info);
     let graphic_queue = graphic_queue_family.get_queue(0);
     let command_pool = device.new_command_pool(graphic_queue_family);
-    let swapchain = device.new_swapchain(None, true).unwrap();
 //texture
     let (image_type, texture, sampler) =
     {
-        let mut img = image::ImageReader::open("data/rocks.png").unwrap().decode().unwrap().into_rgba8();
-        for [r, _, b, _] in img.enumerate_pixels_mut().map(|(_, _, p)| &mut p.0) { std::mem::swap(r, b); }
-        let (tex_width, tex_height) = img.dimensions();
-        let image_type = ImageType { channel: ImageChannelType::BgraSrgb, width: tex_width, height: tex_height, layers: ImageLayers::Single };
-        let mut stage_buffer = device.new_image_buffer(image_type);
-        stage_buffer.write(&img);
+        //one layer per biome texture, they all have to be the same size
+        let images: Vec<_> = biome::textures().into_iter().map(|path|
+        {
+            let mut img = image::ImageReader::open(path).unwrap_or_else(|err| panic!("{path}: {err}")).decode().unwrap().into_rgba8();
+            for [r, _, b, _] in img.enumerate_pixels_mut().map(|(_, _, p)| &mut p.0) { std::mem::swap(r, b); }
+            (path, img)
+        }).collect();
+        let (tex_width, tex_height) = images[0].1.dimensions();
+        let image_type = ImageType { channel: ImageChannelType::BgraSrgb, width: tex_width, height: tex_height, layers: ImageLayers::Array(images.len() as u32) };
         let texture = device.new_image(image_type, ImageUsage::Texture { mipmapping: true });
-        command_pool.new_command_buffer().copy_to_image(&graphic_queue.lock().unwrap(), &stage_buffer, &texture, 0, device.new_fence(false)).mark.wait();
+        let mut stage_buffer = device.new_image_buffer(image_type);
+        for (layer, (path, img)) in images.iter().enumerate()
+        {
+            if img.dimensions() != (tex_width, tex_height) { panic!("{path}: expected {tex_width}x{tex_height} like the other biome textures"); }
+            stage_buffer.write(img);
+            command_pool.new_command_buffer().copy_to_image(&graphic_queue.lock().unwrap(), &stage_buffer, &texture, layer as u32, device.new_fence(false)).mark.wait();
+        }
         let sampler = device.new_sampler(SamplerInfo
         {
             min_filter: SamplerFilter::Linear,
@@ -130,7 +276,8 @@ fn main()
     };
 //text
     let font = Font::new(include_bytes!("../res/LatiniaBlack.ttf"));
-    let chars = Font::digits();
+    let mut chars = Font::digits();
+    chars.extend(consts::MENU_CHARS.chars());
     let (atlas_data, atlas) = Atlas::new(font, 300.0, chars, ATLAS_SIZE, 3);
     let atlas_image_type = ImageType { channel: ImageChannelType::RUnorm, width: ATLAS_SIZE, height: ATLAS_SIZE, layers: ImageLayers::Array(atlas_data.len() as u32) };
     let atlas_image = device.new_image(atlas_image_type, ImageUsage::Texture { mipmapping: false });
@@ -146,44 +293,60 @@ fn main()
             fence.reset();
         }
     }
-    let mut text_vertices = Vec::with_capacity(4 * consts::SCORE_DIGITS);
-    let mut text_indices = Vec::with_capacity(6 * consts::SCORE_DIGITS);
+    //the hud and the menu on top
+    let hud_chars = consts::SCORE_DIGITS + consts::BEST_DIGITS + 1 + consts::BATTERY_HUD_DIGITS + pickup::KINDS.len() * (1 + consts::PICKUP_HUD_DIGITS) + consts::MENU_MAX_CHARS;
+    let mut text_vertices = Vec::with_capacity(4 * hud_chars);
+    let mut text_indices = Vec::with_capacity(6 * hud_chars);
 //dynamic buffer
     let mut buffer_layout = device.new_buffer_type();
     let cam_view = buffer_layout.add_uniforms(1);
     let light_view = buffer_layout.add_uniforms(1);
     let text_uniform_view = buffer_layout.add_uniforms(1);
-    let text_vertex_view = buffer_layout.add_attributes(4 * consts::SCORE_DIGITS as u32);
-    let text_index_view = buffer_layout.add_indices(6 * consts::SCORE_DIGITS as u32);
-//gerenerate and fill flash data
-    let (mut dynamic_buffers, flash_vertex_view, flash_index_view, flash_instance_view) =
+    let text_vertex_view = buffer_layout.add_attributes(4 * hud_chars as u32);
+    let text_index_view = buffer_layout.add_indices(6 * hud_chars as u32);
+//gerenerate and fill pickup data, one mesh and instance list per kind, then the hook's anchor and the beads of its rope
+    let rope_beads = (config.tunables.hook_range / consts::ROPE_BEAD_SPACING) as usize;
+    let (mut dynamic_buffers, pickup_views) =
     {
-        let config = marching_cubes::Config
-        {
-            offset: Vec3(0.0, 0.0, 0.0),
-            radii: Vec3(consts::FLASH_HEIGHT + consts::FLASH_EPS, consts::FLASH_RADIUS + consts::FLASH_EPS, consts::FLASH_RADIUS + consts::FLASH_EPS),
-            resolutions: (consts::FLASH_RESOLUTION, consts::FLASH_RESOLUTION, consts::FLASH_RESOLUTION)
-        };
-        let (vertices, indices) = marching_cubes::build(|v| flash::FlashMold.value(v), config);
-        let vertices: Vec<_> = vertices.into_iter().map(|vertex|
+        let pickup_capacity = (config.tunables.block_spawn_front_distance + config.tunables.block_despawn_back_distance) as u32 * 2;
+        let shapes = pickup::KINDS.iter().map(|kind| (kind.shape, pickup_capacity))
+            .chain([(pickup::Shape::Octahedron(consts::HOOK_ANCHOR_SIZE), 1), (pickup::Shape::Sphere(consts::ROPE_BEAD_SIZE), rope_beads as u32)]);
+        let meshes: Vec<_> = shapes.map(|(shape, capacity)|
         {
-            let vertex = flash::FlashMold.new_vertex(vertex);
-            flash::FlashVertex { pos: vertex.position.into() }
+            let config = marching_cubes::Config
+            {
+                offset: Vec3(0.0, 0.0, 0.0),
+                radii: shape.bounds() + Vec3(consts::FLASH_EPS, consts::FLASH_EPS, consts::FLASH_EPS),
+                resolutions: (consts::FLASH_RESOLUTION, consts::FLASH_RESOLUTION, consts::FLASH_RESOLUTION)
+            };
+            let (vertices, indices) = marching_cubes::build(|v| shape.value(v), config);
+            let vertices: Vec<_> = vertices.into_iter().map(|vertex|
+            {
+                let vertex = shape.new_vertex(vertex);
+                flash::FlashVertex { pos: vertex.position.into() }
+            }).collect();
+            (vertices, indices, capacity)
         }).collect();
-        let vertex_view = buffer_layout.add_attributes(vertices.len() as u32);
-        let index_view = buffer_layout.add_indices(indices.len() as u32);
-        let instance_view = buffer_layout.add_attributes((consts::BLOCK_SPAWN_FRONT_DISTANCE + consts::BLOCK_DESPAWN_BACK_DISTANCE) as u32 * 2);
+        let views: Vec<_> = meshes.iter().map(|(vertices, indices, capacity)|
+        (
+            buffer_layout.add_attributes(vertices.len() as u32),
+            buffer_layout.add_indices(indices.len() as u32),
+            buffer_layout.add_attributes(*capacity)
+        )).collect();
         let buffer_layout = buffer_layout.build();
         let mut buffers = SwapchainCycle::<2, _>::new(&mut || device.new_buffer(&buffer_layout, BufferUsage::Dynamic));
         for buffer in buffers.slice()
         {
             let mut map = buffer.map();
-            map.write_attributes(&vertex_view, 0, &vertices);
-            map.write_indices(&index_view, 0, &indices);
+            for ((vertices, indices, _), (vertex_view, index_view, _)) in meshes.iter().zip(&views)
+            {
+                map.write_attributes(vertex_view, 0, vertices);
+                map.write_indices(index_view, 0, indices);
+            }
         }
-        (buffers, vertex_view, index_view, instance_view)
+        (buffers, views)
     };
-    let mut flashes = Vec::new();
+    let mut pickup_instances: Vec<Vec<flash::FlashInstance>> = pickup_views.iter().map(|_| Vec::new()).collect();
 //descriptors
     let cam_descriptor_layout = device.new_descriptor_set_layout(0, vec![DescriptorBindingInfo::from_struct::<CamBinding>(1, DescriptorVisibility::vertex())]);
     let light_descriptor_layout = device.new_descriptor_set_layout(1, vec![DescriptorBindingInfo::from_struct::<LightBinding>(1, DescriptorVisibility::graphic_full())]);
@@ -200,39 +363,22 @@ fn main()
     let mut tex_descriptor = device.new_descriptor_sets(&[(&tex_descriptor_layout, 1)]).remove(0).remove(0);
     tex_descriptor.update_sampler(0, &[&texture], &sampler);
 //cave
-    let seed = 0; //(std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH)).unwrap().as_nanos() as u32;
-    let mold_gen = ||
-    {
-        let mut billow = noise::Billow::new().set_seed(seed);
-        billow.octaves = consts::CAVE_GEN_OCTAVES;
-        billow.frequency = consts::CAVE_GEN_FREQUENCY;
-        billow.lacunarity = consts::CAVE_GEN_LUCUNARITY;
-        billow.persistence = consts::CAVE_GEN_PERSISTANCE;
-        cave::Cave::new(billow, noise::Perlin::new().set_seed(seed), consts::CAVE_GEN_BIAS)
-    };
-    let mold = mold_gen();
-    let light_perlin = noise::Perlin::new();
+    let cache = cache::Cache::new(&config);
 	let blocks = std::cell::RefCell::new(HashMap::<i32, cave::CylinderBlock>::new());
-    let mut blocks_requested = HashSet::new();
-    let generators = vec!
-    [
-        cave::BlockGenerator::new(&device, graphic_queue_family_info, mold_gen()),
-        cave::BlockGenerator::new(&device, graphic_queue_family_info, mold_gen()),
-        cave::BlockGenerator::new(&device, graphic_queue_family_info, mold_gen())
-    ];
-    let mut generator_index = 0;
-//cam
-    let mut cam = camera::Camera::new();
-    cam.build_projection(width as f32 / height as f32);
-    cam.pos.0 = mold.x0();
-    cam.pos.1 = mold.y0();
+    let mut blocks_requested = HashMap::new();
+    let mut generator = cave::BlockGenerator::new(&device, graphic_queue_family_info, || config.mold(), cache.clone());
+//simulation
+    let mut sim = sim::Simulation::new(config.mold(), config.seed, config.probabilities, cache);
+    sim.cam.build_projection(width as f32 / height as f32);
+    let mut input = sim::Input::default();
+    //headless runs take no input, so they leave the bindings file alone
+    let bindings = if config.headless.is_some() { bindings::Bindings::new() } else { bindings::Bindings::load(&config.bindings) };
+    let mut controls = bindings::Controls::new(bindings);
+    let mut clock = sim::Clock::new(config.tick_rate);
 //main graphic stuff
     let msaa = Msaa::X4;
-    //image buffers
-    let color_buffer = device.new_image(ImageType { channel: Swapchain::IMAGE_CHANNEL_TYPE, width, height, layers: ImageLayers::Single }, ImageUsage::Attachment { depth: false, samples: msaa, texture: false, transfer_src: false });
-    let depth_buffer = device.new_image(ImageType { channel: ImageChannelType::DSfloat, width, height, layers: ImageLayers::Single }, ImageUsage::Attachment { depth: true, samples: msaa, texture: false, transfer_src: false });
-    //renderpass & pipeline creation
-    let render_pass = device.new_render_pass
+    //renderpass & pipeline creation, offscreen passes resolve into an image to copy out of instead of the swapchain
+    let new_render_pass = |offscreen: bool| device.new_render_pass
     (
         RenderPassInfo
         {
@@ -247,7 +393,18 @@ fn main()
                     initial_layout: ImageLayout::Undefined,
                     final_layout: ImageLayout::Attachment
                 },
-                RenderPassColorAttachment::Swapchain(SwapchainLoad::DontCare)
+                if !offscreen { RenderPassColorAttachment::Swapchain(SwapchainLoad::DontCare) } else
+                {
+                    RenderPassColorAttachment::Image
+                    {
+                        image_channel_type: Swapchain::IMAGE_CHANNEL_TYPE,
+                        samples: Msaa::X1,
+                        load: ColorAttachmentLoad::DontCare,
+                        store: AttachmentStore::Store,
+                        initial_layout: ImageLayout::Undefined,
+                        final_layout: ImageLayout::Attachment
+                    }
+                }
             ],
             depth_attachment: Some(RenderPassDepthAttachment
             {
@@ -270,52 +427,270 @@ fn main()
             ]
         }
     );
-    let framebuffers = swapchain.new_objects(&mut |index| device.new_framebuffer(&render_pass, &[FramebufferAttachment::image(&color_buffer), FramebufferAttachment::Swapchain(swapchain.get_image(index)), FramebufferAttachment::image(&depth_buffer)]));
+    let render_pass = new_render_pass(window.is_none());
     let pipeline_layout = device.new_pipeline_layout(&[&cam_descriptor_layout, &light_descriptor_layout, &tex_descriptor_layout], None);
     let text_pipeline_layout = device.new_pipeline_layout(&[&text_descriptor_layout], None);
-    let mut pipeline_info = PipelineInfo
+    let new_pipelines = |render_pass: &RenderPass, width: u32, height: u32|
     {
-        view: Some(ViewInfo::full(width, height)),
-        topology: PipelineTopology::TriangleList,
-        samples: msaa,
-        min_sample_shading: None,
-        line_width: 1.0,
-        polygon: PipelinePolygon::Fill,
-        cull: PipelineCull::Back,
-        depth_test: DepthTest::Normal,
-        blend: false
+        let mut pipeline_info = PipelineInfo
+        {
+            view: Some(ViewInfo::full(width, height)),
+            topology: PipelineTopology::TriangleList,
+            samples: msaa,
+            min_sample_shading: None,
+            line_width: 1.0,
+            polygon: PipelinePolygon::Fill,
+            cull: PipelineCull::Back,
+            depth_test: DepthTest::Normal,
+            blend: false
+        };
+        //pipeline_info.cull = PipelineCull::None;
+        let cave_pipeline = device.new_pipeline
+        (
+            render_pass, 0,
+            CAVE_VERTEX, CAVE_FRAGMENT,
+            &[AttributeGroupInfo::from::<Vertex>()], &pipeline_layout,
+            &pipeline_info
+        );
+        let flash_pipeline = device.new_pipeline
+        (
+            render_pass, 0,
+            FLASH_VERTEX, FLASH_FRAGMENT,
+            &[AttributeGroupInfo::from::<flash::FlashVertex>(), AttributeGroupInfo::from::<flash::FlashInstance>()], &pipeline_layout,
+            &pipeline_info
+        );
+        let bg_pipeline = device.new_pipeline
+        (
+            render_pass, 0,
+            BG_VERTEX, BG_FRAGMENT,
+            &[], &pipeline_layout,
+            &pipeline_info
+        );
+        pipeline_info.depth_test = DepthTest::None;
+        pipeline_info.blend = true;
+        let text_pipeline = device.new_pipeline
+        (
+            render_pass, 0,
+            TEXT_VERTEX, TEXT_FRAGMENT,
+            &[AttributeGroupInfo::from::<TextVertex>()], &text_pipeline_layout,
+            &pipeline_info
+        );
+        (cave_pipeline, flash_pipeline, bg_pipeline, text_pipeline)
     };
-    //pipeline_info.cull = PipelineCull::None;
-    let cave_pipeline = device.new_pipeline
-    (
-        &render_pass, 0,
-        CAVE_VERTEX, CAVE_FRAGMENT,
-        &[AttributeGroupInfo::from::<Vertex>()], &pipeline_layout,
-        &pipeline_info
-    );
-    let flash_pipeline = device.new_pipeline
-    (
-        &render_pass, 0,
-        FLASH_VERTEX, FLASH_FRAGMENT,
-        &[AttributeGroupInfo::from::<flash::FlashVertex>(), AttributeGroupInfo::from::<flash::FlashInstance>()], &pipeline_layout,
-        &pipeline_info
-    );
-    let bg_pipeline = device.new_pipeline
-    (
-        &render_pass, 0,
-        BG_VERTEX, BG_FRAGMENT,
-        &[], &pipeline_layout,
-        &pipeline_info
-    );
-    pipeline_info.depth_test = DepthTest::None;
-    pipeline_info.blend = true;
-    let text_pipeline = device.new_pipeline
-    (
-        &render_pass, 0,
-        TEXT_VERTEX, TEXT_FRAGMENT,
-        &[AttributeGroupInfo::from::<TextVertex>()], &text_pipeline_layout,
-        &pipeline_info
-    );
+    let mut pipelines = new_pipelines(&render_pass, width, height);
+    //multisampled attachments and the resolved image for rendering without the swapchain
+    let new_offscreen = |render_pass: &RenderPass, width: u32, height: u32|
+    {
+        let color_buffer = device.new_image(ImageType { channel: Swapchain::IMAGE_CHANNEL_TYPE, width, height, layers: ImageLayers::Single }, ImageUsage::Attachment { depth: false, samples: msaa, texture: false, transfer_src: false });
+        let depth_buffer = device.new_image(ImageType { channel: ImageChannelType::DSfloat, width, height, layers: ImageLayers::Single }, ImageUsage::Attachment { depth: true, samples: msaa, texture: false, transfer_src: false });
+        let image = device.new_image(ImageType { channel: Swapchain::IMAGE_CHANNEL_TYPE, width, height, layers: ImageLayers::Single }, ImageUsage::Attachment { depth: false, samples: Msaa::X1, texture: false, transfer_src: true });
+        let framebuffer = device.new_framebuffer(render_pass, &[FramebufferAttachment::image(&color_buffer), FramebufferAttachment::image(&image), FramebufferAttachment::image(&depth_buffer)]);
+        (framebuffer, image, (color_buffer, depth_buffer))
+    };
+    //takes in generated blocks and requests the ones the simulation needs, remeshing them in more detail as they come closer
+    let update_blocks = |sim: &sim::Simulation<cave::CaveNoise>, generator: &cave::BlockGenerator, blocks_requested: &mut HashMap<i32, usize>, graveyard: &mut Vec<cave::CylinderBlock>|
+    {
+        //fetch generated blocks
+        for block in generator.receive()
+        {
+            if blocks_requested.get(&block.z) == Some(&block.lod) { blocks_requested.remove(&block.z); }
+            if !sim.blocks.contains_key(&block.z) { graveyard.push(block); }
+            //a more detailed version may have overtaken this one
+            else if blocks.borrow().get(&block.z).is_some_and(|current| current.lod <= block.lod) { graveyard.push(block); }
+            else if let Some(block) = blocks.borrow_mut().insert(block.z, block) { graveyard.push(block); }
+        }
+        //cancel requests the player has left behind
+        blocks_requested.retain(|z, _| sim.blocks.contains_key(z) || !generator.cancel(*z));
+        //check blocks needed
+        let cam_norm = sim.cam_norm();
+        generator.focus(cam_norm);
+        for block_needed in sim.blocks.keys()
+        {
+            let lod = cave::lod(*block_needed, cam_norm);
+            let current = blocks.borrow().get(block_needed).map(|block| block.lod).into_iter().chain(blocks_requested.get(block_needed).copied()).min();
+            if current.is_none_or(|current| lod < current)
+            {
+                generator.request(*block_needed, lod);
+                blocks_requested.insert(*block_needed, lod);
+            }
+        }
+        //check blocks unneeded
+        let blocks_remove: Vec<_> = blocks.borrow().keys().filter(|z| !sim.blocks.contains_key(z)).copied().collect();
+        for block in blocks_remove { graveyard.push(blocks.borrow_mut().remove(&block).unwrap()); }
+    };
+    //fills the dynamic buffer and records the whole frame, into the swapchain and offscreen alike
+    let mut draw = |command_buffer: &mut CommandBuffer, render_pass: &RenderPass, framebuffer: &Framebuffer, dynamic_buffer: &mut Buffer, uniform_descriptor: &[Vec<DescriptorSet>], pipelines: &(Pipeline, Pipeline, Pipeline, Pipeline), sim: &sim::Simulation<cave::CaveNoise>, pose: camera::Pose, text: &[TextLine], (width, height): (u32, u32)|
+    {
+        let (cave_pipeline, flash_pipeline, bg_pipeline, text_pipeline) = pipelines;
+        for instances in &mut pickup_instances { instances.clear(); }
+        for pickup in sim.pickups() { pickup_instances[pickup.kind].push(flash::FlashInstance { offset: pickup.pos.into(), color: pickup.color.into() }); }
+        if let Some(hook) = sim.cam.hook
+        {
+            let [anchor, rope] = [pickup::KINDS.len(), pickup::KINDS.len() + 1];
+            pickup_instances[anchor].push(flash::FlashInstance { offset: hook.anchor.into(), color: Vec3::from(consts::HOOK_ANCHOR_COLOR).into() });
+            //from the middle of the player, so the rope does not start right in front of the eye
+            let to_anchor = hook.anchor - pose.pos;
+            let beads = ((to_anchor.norm() / consts::ROPE_BEAD_SPACING) as usize).min(rope_beads);
+            for i in 1..beads { pickup_instances[rope].push(flash::FlashInstance { offset: (pose.pos + to_anchor * (i as f32 / beads as f32)).into(), color: Vec3::from(consts::ROPE_COLOR).into() }); }
+        }
+        text_vertices.clear();
+        text_indices.clear();
+        for line in text { layout_text(&atlas, line, &mut text_vertices, &mut text_indices); }
+        {
+            let tunables = tunables::get();
+            let light = sim.light();
+            let (proj, trans) = sim.cam.mats(pose);
+            let dir = trans.transpose() * Mat4::rotation_x(consts::LIGHT_ANGLE) * Vec4(0.0, 0.0, 1.0, 0.0);
+            let dir = (dir.0, dir.1, dir.2);
+            let mut map = dynamic_buffer.map();
+            map.write_uniforms(&cam_view, 0, &[CamBinding
+            {
+                mat: proj * trans,
+                bg_radius: tunables.bg_radius(),
+                bg_back_distance: tunables.bg_back_distance(),
+                bg_front_distance: tunables.bg_front_distance(),
+                _padding0: Default::default()
+            }]);
+            map.write_uniforms(&light_view, 0, &[LightBinding
+            {
+                z_bias: sim.z_bias(),
+                color: (Vec3::from(consts::LIGHT_COLOR) * light).into(),
+                battery: sim.battery,
+                pos: (pose.pos + Vec3(0.0, -consts::FIGUR_HEIGHT, 0.0)).into(),
+                dir,
+                cos_angle_inner: consts::LIGHT_ANGLE_INNER.cos(),
+                cos_angle_outer: consts::LIGHT_ANGLE_OUTER.cos(),
+                ambient: biome::ambient(pose.pos.2),
+                flash_ambient: sim.ambient_flash.into(),
+                biome_length: consts::BIOME_LENGTH,
+                biome_blend: consts::BIOME_BLEND,
+                biome_count: biome::BIOMES.len() as f32,
+                _padding1: Default::default(),
+                _padding2: Default::default(),
+                _padding3: Default::default(),
+                _padding4: Default::default()
+            }]);
+            for ((_, _, instance_view), instances) in pickup_views.iter().zip(&pickup_instances) { map.write_attributes(instance_view, 0, instances); }
+            map.write_attributes(&text_vertex_view, 0, &text_vertices);
+            map.write_indices(&text_index_view, 0, &text_indices);
+            map.write_uniforms(&text_uniform_view, 0, &[TextBinding { aspect: width as f32 / height as f32, height: consts::TEXT_HEIGHT }]);
+        }
+
+        let mut record = command_buffer.record();
+        let mut pass = record.render_pass(render_pass, framebuffer);
+        pass
+            .bind_descriptor_sets(&pipeline_layout, &[&uniform_descriptor[0][0], &uniform_descriptor[1][0], &tex_descriptor])
+            .bind_pipeline(cave_pipeline);
+        for block in blocks.borrow().values()
+        {
+            pass
+                .bind_attributes(0, [AttributeBinding::from::<Vertex>(&block.buffer, &block.vertex_view)])
+                .bind_indices(IndexBinding::from(&block.buffer, &block.index_view))
+                .draw(DrawMode::index(block.index_view.count()));
+        }
+        pass.bind_pipeline(flash_pipeline);
+        for ((vertex_view, index_view, instance_view), instances) in pickup_views.iter().zip(&pickup_instances)
+        {
+            pass
+                .bind_attributes(0, [
+                    AttributeBinding::from::<flash::FlashVertex>(&dynamic_buffer, vertex_view),
+                    AttributeBinding::from::<flash::FlashInstance>(&dynamic_buffer, instance_view)
+                ])
+                .bind_indices(IndexBinding::from(&dynamic_buffer, index_view))
+                .draw(DrawMode::index_instanced(index_view.count(), instances.len() as u32));
+        }
+        pass
+            .bind_pipeline(bg_pipeline)
+            .draw(DrawMode::vertex(36));
+        pass
+            .bind_pipeline(text_pipeline)
+            .bind_descriptor_sets(&text_pipeline_layout, &[&uniform_descriptor[2][0]])
+            .bind_attributes(0, [AttributeBinding::from::<TextVertex>(&dynamic_buffer, &text_vertex_view)])
+            .bind_indices(IndexBinding::from(&dynamic_buffer, &text_index_view))
+            .draw(DrawMode::index(text_indices.len() as u32));
+    };
+
+//headless, renders a fixed number of frames at a fixed rate offscreen and writes them as numbered pngs
+    if let Some(frame_count) = config.headless
+    {
+        //the frame images take the place of the swapchain image and get copied out after every frame,
+        //the encoder holds at most SCREENSHOT_QUEUE copies, so the image drawn into next has always been read already
+        let frame_images: Vec<_> = (0..consts::SCREENSHOT_QUEUE + 1).map(|_| new_offscreen(&render_pass, width, height)).collect();
+        let mut command_buffer = command_pool.new_command_buffer();
+        let rendered = device.new_fence(false);
+        let mut graveyard = Vec::new();
+        //every frame gets written, so the render loop waits whenever the encoder falls behind
+        let encoder = screenshot::Encoder::new(&device, consts::SCREENSHOT_QUEUE, true);
+        //without a replay the camera flies straight down the cave
+        let flythrough = sim::Input { forward: true, noclip: Some(true), ..Default::default() };
+        std::fs::create_dir_all(&config.frames).unwrap_or_else(|err| panic!("{}: {err}", config.frames));
+        for frame in 0..frame_count
+        {
+            clock.advance(1.0 / config.frame_rate);
+            let replay_over = match &mut player
+            {
+                Some(frames) =>
+                {
+                    while let Some(&(dt, _)) = frames.peek() && clock.take(dt)
+                    {
+                        let (dt, frame_input) = frames.next().unwrap();
+                        sim.step(dt, &frame_input);
+                    }
+                    frames.peek().is_none()
+                },
+                None =>
+                {
+                    while clock.take(clock.tick) { sim.step(clock.tick, &flythrough); }
+                    false
+                }
+            };
+            //wait for every block in the detail it should have, so the frames don't depend on how fast the generator is
+            loop
+            {
+                update_blocks(&sim, &generator, &mut blocks_requested, &mut graveyard);
+                if blocks_requested.is_empty() { break; }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            let (framebuffer, frame_image, _attachments) = &frame_images[frame as usize % frame_images.len()];
+            draw(&mut command_buffer, &render_pass, framebuffer, dynamic_buffers.get_next(), uniform_descriptors.get_next(), &pipelines, &sim, sim.pose(clock.alpha()), &hud_lines(&sim, None), (width, height));
+            let graphic_queue = graphic_queue.lock().unwrap();
+            command_buffer.submit(&graphic_queue, [], [], Some(&rendered));
+            rendered.wait();
+            rendered.reset();
+            graveyard.clear();
+            encoder.capture(&command_pool, &graphic_queue, CopyImageSource::Image(frame_image), (width, height), format!("{}/{frame:05}.png", config.frames), screenshot::metadata(&sim, &config));
+            if replay_over { break; }
+        }
+        encoder.finish();
+        finish_run(&sim, None, None);
+        print_stats(generator.shutdown());
+        device.idle();
+        return;
+    }
+
+//window and swapchain
+    let (window, mut event_loop) = (window.as_ref().unwrap(), event_loop.unwrap());
+    let mut swapchain = device.new_swapchain(None, true).unwrap();
+    //everything sized like the window, rebuilt together with the swapchain whenever that changes
+    let new_framebuffers = |swapchain: &Swapchain, width: u32, height: u32|
+    {
+        let color_buffer = device.new_image(ImageType { channel: Swapchain::IMAGE_CHANNEL_TYPE, width, height, layers: ImageLayers::Single }, ImageUsage::Attachment { depth: false, samples: msaa, texture: false, transfer_src: false });
+        let depth_buffer = device.new_image(ImageType { channel: ImageChannelType::DSfloat, width, height, layers: ImageLayers::Single }, ImageUsage::Attachment { depth: true, samples: msaa, texture: false, transfer_src: false });
+        let framebuffers = swapchain.new_objects(&mut |index| device.new_framebuffer(&render_pass, &[FramebufferAttachment::image(&color_buffer), FramebufferAttachment::Swapchain(swapchain.get_image(index)), FramebufferAttachment::image(&depth_buffer)]));
+        (framebuffers, (color_buffer, depth_buffer))
+    };
+    //the attachments only have to outlive the framebuffers using them
+    let (mut framebuffers, mut _attachments) = new_framebuffers(&swapchain, width, height);
+    let mut resized = false;
+//game states, replays skip the title
+    let mut screen = if player.is_some() { menu::Screen::Playing } else { menu::Screen::Title };
+    let mut recorder: Option<replay::Recorder> = None;
+    //seed and screen of the run to start at the next frame
+    let mut restart: Option<(u32, menu::Screen)> = None;
+    grab_cursor(window, screen == menu::Screen::Playing);
+//personal best
+    let mut leaderboard = scores::Leaderboard::load();
+    let mut best_digits = best_digits(&leaderboard);
     //synchronization elements
     struct SyncStuff
     {
@@ -334,13 +709,14 @@ fn main()
         graveyard: Vec::new()
     });
 
-//screenshots
-    let mut shot = false;
-    let mut shot_command_buffer: Option<CommandBuffer> = None;
+//screenshots, with the scale of the one to take
+    let mut shot: Option<u32> = None;
+    let offscreen_pass = new_render_pass(true);
+    let mut encoder = screenshot::Encoder::new(&device, consts::SCREENSHOT_QUEUE, false);
+    //dropped because the encoder was full, reported with the other stats
+    let mut shots_skipped = 0;
 //game loop
     let mut fps = FPS::new(None);
-    let mut time = -consts::WAIT_TIME;
-    let mut ambient_flash = Vec3(0.0, 0.0, 0.0);
     window.set_visible(true);
     use winit::platform::run_on_demand::EventLoopExtRunOnDemand;
     event_loop.run_on_demand(|event, control_flow|
@@ -349,234 +725,232 @@ fn main()
         {
             event::Event::WindowEvent { window_id: _, event } if event != event::WindowEvent::RedrawRequested =>
             {
-                match event
+                let button = match event
                 {
-                    event::WindowEvent::CloseRequested => control_flow.exit(),
-                    event::WindowEvent::KeyboardInput { event, .. } =>
+                    event::WindowEvent::CloseRequested =>
                     {
-                        if let PhysicalKey::Code(keycode) = event.physical_key
+                        control_flow.exit();
+                        None
+                    },
+                    event::WindowEvent::Resized(_) =>
+                    {
+                        resized = true;
+                        None
+                    },
+                    event::WindowEvent::KeyboardInput { event, .. } => match event.physical_key
+                    {
+                        PhysicalKey::Code(keycode) => Some((bindings::Button::Key(keycode), event.state)),
+                        _ => None
+                    },
+                    event::WindowEvent::MouseInput { state, button, .. } => Some((bindings::Button::Mouse(button), state)),
+                    _ => None
+                };
+                if let Some((button, state)) = button
+                    && let Some((action, pressed)) = controls.button(button, state == ElementState::Pressed)
+                {
+                    match action
+                    {
+                        bindings::Action::Quit => if pressed { control_flow.exit(); },
+                        bindings::Action::Screenshot => if pressed { shot = Some(1); },
+                        bindings::Action::ScreenshotLarge => if pressed { shot = Some(config.screenshot_scale); },
+                        //toggles the configured mode, or borderless if the config asks for a window
+                        bindings::Action::Fullscreen => if pressed
                         {
-                            match keycode
+                            let mode = if config.fullscreen == config::Fullscreen::Off { config::Fullscreen::Borderless } else { config.fullscreen };
+                            window.set_fullscreen(if window.fullscreen().is_some() { None } else { fullscreen(window, mode) });
+                        },
+                        bindings::Action::Pause => if pressed
+                        {
+                            screen = match screen
                             {
-                                KeyCode::KeyW => cam.forward = event.state == ElementState::Pressed,
-                                KeyCode::KeyS => cam.backward = event.state == ElementState::Pressed,
-                                KeyCode::KeyA => cam.left = event.state == ElementState::Pressed,
-                                KeyCode::KeyD => cam.right = event.state == ElementState::Pressed,
-                                KeyCode::Space => cam.jump(),
-                                KeyCode::KeyK => cam.does_physics = false,
-                                KeyCode::KeyL => cam.does_physics = true,
-                                KeyCode::Escape => control_flow.exit(),
-                                KeyCode::KeyP => if event.state == ElementState::Pressed { shot = true },
-                                _ => {}
+                                menu::Screen::Playing => menu::Screen::Paused,
+                                menu::Screen::Paused => menu::Screen::Playing,
+                                other => other
                             };
-                        }
-                    },
-                    _ => {}
-                };
+                            grab_cursor(window, screen == menu::Screen::Playing);
+                        },
+                        bindings::Action::Confirm => if pressed
+                        {
+                            match screen
+                            {
+                                menu::Screen::Title =>
+                                {
+                                    //the first run may go to the replay path from the config, later ones get their own
+                                    let path = config.record.take();
+                                    recorder = Some(replay::Recorder::create(path.as_deref(), &config));
+                                    screen = menu::Screen::Playing;
+                                    grab_cursor(window, true);
+                                },
+                                menu::Screen::GameOver(_) => restart = Some((config.seed, menu::Screen::Playing)),
+                                _ => {}
+                            }
+                        },
+                        bindings::Action::NewSeed => if pressed
+                        {
+                            match screen
+                            {
+                                menu::Screen::Title => restart = Some((config::random_seed(), menu::Screen::Title)),
+                                menu::Screen::GameOver(_) => restart = Some((config::random_seed(), menu::Screen::Playing)),
+                                _ => {}
+                            }
+                        },
+                        _ => input.action(action, pressed)
+                    }
+                }
             },
             winit::event::Event::DeviceEvent { device_id: _, event } =>
             {
                 match event
                 {
-                    winit::event::DeviceEvent::MouseMotion { delta } =>
+                    winit::event::DeviceEvent::MouseMotion { delta } => if screen == menu::Screen::Playing
                     {
-                        cam.phi += consts::MOUSE_SENSITIVITY * delta.0 as f32;
-                        cam.theta -= consts::MOUSE_SENSITIVITY * delta.1 as f32;
-                        cam.theta = cam.theta.max(-std::f32::consts::FRAC_PI_2).min(std::f32::consts::FRAC_PI_2);
+                        input.look.0 += delta.0 as f32;
+                        input.look.1 += delta.1 as f32;
                     },
                     _ => {}
                 }
             },
             winit::event::Event::WindowEvent { event: event::WindowEvent::RedrawRequested, .. } =>
             {
+                //start over, everything from the last run is torn down
+                if let Some((seed, next)) = restart.take()
+                {
+                    config.seed = seed;
+                    for (_, block) in blocks.borrow_mut().drain() { sync_stuff.get_current().graveyard.push(block); }
+                    blocks_requested.clear();
+                    let cache = cache::Cache::new(&config);
+                    let new_generator = cave::BlockGenerator::new(&device, graphic_queue_family_info, || config.mold(), cache.clone());
+                    print_stats(std::mem::replace(&mut generator, new_generator).shutdown());
+                    sim = sim::Simulation::new(config.mold(), config.seed, config.probabilities, cache);
+                    sim.cam.build_projection(width as f32 / height as f32);
+                    input.clear_impulses();
+                    clock = sim::Clock::new(config.tick_rate);
+                    player = None;
+                    if next == menu::Screen::Playing { recorder = Some(replay::Recorder::create(None, &config)); }
+                    screen = next;
+                    grab_cursor(window, screen == menu::Screen::Playing);
+                }
                 //logic
                 let dt = fps.dt();
-                time += dt;
-                ambient_flash = ambient_flash * consts::FLASH_AMBIENT_DECAY.powf(dt);
-                cam.logic(dt, &mold);
-                let mut blocks_changed = false;
-
-                let cam_norm = cam.pos.2 / consts::BLOCK_LENGTH;
-                for block_z in &[cam_norm.floor() as i32, cam_norm.ceil() as i32]
+                if screen == menu::Screen::Playing { clock.advance(dt); }
+                match &mut player
                 {
-                    if let hash_map::Entry::Occupied(mut entry) = blocks.borrow_mut().entry(*block_z)
+                    Some(frames) =>
                     {
-                        let block = entry.get_mut();
-                        for i in 0..block.flashes.len()
+                        while let Some(&(dt, _)) = frames.peek() && clock.take(dt)
                         {
-                            if (cam.pos + block.flashes[i].0 * (-1.0)).norm() < consts::PICKUP_RANGE
-                            {
-                                time -= consts::FLASH_POWER;
-                                ambient_flash = ambient_flash + block.flashes[i].1 * consts::FLASH_AMBIENT_POWER;
-                                block.flashes.remove(i);
-                                blocks_changed = true;
-                                break;
-                            }
+                            let (dt, frame_input) = frames.next().unwrap();
+                            sim.step(dt, &frame_input);
                         }
-                    }
-                }
-                //fetch generated blocks
-                for generator in &generators
-                {
-                    for block in generator.receive()
-                    {
-                        blocks_changed = true;
-                        blocks_requested.remove(&block.z);
-                        if let Some(block) = blocks.borrow_mut().insert(block.z, block)
+                        if frames.peek().is_none() && screen == menu::Screen::Playing
                         {
-                            println!("Regenerated block {}!", block.z);
-                            sync_stuff.get_current().graveyard.push(block);
+                            screen = menu::Screen::GameOver(finish_run(&sim, None, None));
+                            grab_cursor(window, false);
                         }
+                    },
+                    None => while clock.take(clock.tick)
+                    {
+                        if let Some(recorder) = &mut recorder { recorder.frame(clock.tick, &input); }
+                        sim.step(clock.tick, &input);
+                        input.clear_impulses();
                     }
                 }
-                //check blocks needed
-                let range = (cam_norm.round() as i32)..=(cam_norm.round() as i32 + consts::BLOCK_SPAWN_FRONT_DISTANCE);
-                for block_needed in range
+                let pose = sim.pose(clock.alpha());
+                update_blocks(&sim, &generator, &mut blocks_requested, &mut sync_stuff.get_current().graveyard);
+                if sim.over() && screen == menu::Screen::Playing
                 {
-                    if blocks.borrow().values().find(|block| block.z == block_needed).is_none() && blocks_requested.iter().find(|block| **block == block_needed).is_none()
-                    {
-                        generators[generator_index].request(block_needed);
-                        generator_index = (generator_index + 1) % generators.len();
-                        blocks_requested.insert(block_needed);
-                    }
+                    let rank = finish_run(&sim, recorder.take(), player.is_none().then_some(&mut leaderboard));
+                    best_digits = best_digits(&leaderboard);
+                    screen = menu::Screen::GameOver(rank);
+                    grab_cursor(window, false);
                 }
-                //check blocks unneeded
-                let mut blocks_remove = Vec::new();
-                for block in blocks.borrow().values()
+                let mut text = if screen == menu::Screen::Title { Vec::new() } else { hud_lines(&sim, best_digits.as_deref()) };
+                //text.vert maps x = aspect / height and y = 1 - 1 / height to the middle of the screen
+                let middle = (width as f32 / height as f32 / consts::TEXT_HEIGHT, 1.0 - 1.0 / consts::TEXT_HEIGHT);
+                for menu::Line { text: line, row, scale } in screen.lines(&sim, &controls)
                 {
-                    if (cam_norm - block.z as f32).floor() as i32 >= consts::BLOCK_DESPAWN_BACK_DISTANCE { blocks_remove.push(block.z); }
+                    text.push(TextLine { text: line, offset: (middle.0, middle.1 + row * consts::MENU_LINE_SPACING), scale, color: consts::TEXT_COLOR, solid: false, centered: true });
                 }
-                if !blocks_remove.is_empty() { blocks_changed = true; }
-                for block in blocks_remove { sync_stuff.get_current().graveyard.push(blocks.borrow_mut().remove(&block).unwrap()); }
-                if blocks_changed
+                //rebuild everything sized like the window, a minimized window has nothing to draw to
+                if resized
                 {
-                    flashes.clear();
-                    for block in blocks.borrow().values()
+                    let size = window.inner_size();
+                    if size.width == 0 || size.height == 0
                     {
-                        for flash in block.flashes.iter()
-                        {
-                            flashes.push(flash::FlashInstance { offset: flash.0.into(), color: flash.1.into() });
-                        }
+                        window.request_redraw();
+                        return;
                     }
+                    device.idle();
+                    (width, height) = size.into();
+                    swapchain = device.new_swapchain(Some(&swapchain), true).unwrap();
+                    (framebuffers, _attachments) = new_framebuffers(&swapchain, width, height);
+                    pipelines = new_pipelines(&render_pass, width, height);
+                    sim.cam.build_projection(width as f32 / height as f32);
+                    resized = false;
                 }
-                //compute score
-                let z_bias = consts::Z_BIAS_OFFSET + consts::C * time.max(0.0);
-                let score = consts::MAX_BIAS - (z_bias - cam.pos.2);
-                if score <= 0.0 { control_flow.exit(); }
-                let score_digits = format!("{:03}", score.round() as u32);
-                text_vertices.clear();
-                text_indices.clear();
-                atlas.text
-                (
-                    &score_digits,
-                    Layout { width: std::f32::MAX, align: Align::Left, auto_wrap: false },
-                    &mut |i| text_indices.push(i as u16),
-                    &mut |(cx, cy, cl), p: (f32, f32)| text_vertices.push(TextVertex { position: p.into(), tex_coords: (cx, cy, cl as f32).into() })
-                );
                 //render
                 let SyncStuff { command_buffer, image_available, rendering_finished, may_begin_drawing, graveyard } = sync_stuff.get_next();
                 may_begin_drawing.wait();
-                may_begin_drawing.reset();
                 graveyard.clear();
                 let maybe_image_index = swapchain.acquire_next_image(Some(&image_available), None);
                 let dynamic_buffer = dynamic_buffers.get_next();
                 let uniform_descriptor = uniform_descriptors.get_next();
 
+                //an out of date swapchain gets rebuilt next frame, the fence stays signaled so the frame slot can be reused
+                if maybe_image_index.is_err() { resized = true; }
+                //a plain screenshot copies the frame that is shown, without one it is dropped
+                let window_shot = shot.take_if(|scale| *scale == 1).is_some();
+                if window_shot && maybe_image_index.is_err()
+                {
+                    eprintln!("Skipped a screenshot, the window could not be drawn to");
+                    shots_skipped += 1;
+                }
                 if let Ok(image_index) = maybe_image_index
                 {
-                    {
-                        let light_on = light_perlin.get([time as f64 * consts::LIGHT_FREQUENCY, 0.0]) + consts::LIGHT_BIAS > 0.0;
-                        let (proj, trans) = cam.mats();
-                        let dir = trans.transpose() * Mat4::rotation_x(consts::LIGHT_ANGLE) * Vec4(0.0, 0.0, 1.0, 0.0);
-                        let dir = (dir.0, dir.1, dir.2);
-                        let mut map = dynamic_buffer.map();
-                        map.write_uniforms(&cam_view, 0, &[CamBinding { mat: proj * trans }]);
-                        map.write_uniforms(&light_view, 0, &[LightBinding
-                        {
-                            z_bias,
-                            color: if light_on { consts::LIGHT_COLOR } else { (0.0, 0.0, 0.0) },
-                            pos: (cam.pos + Vec3(0.0, -consts::FIGUR_HEIGHT, 0.0)).into(),
-                            dir,
-                            cos_angle_inner: consts::LIGHT_ANGLE_INNER.cos(),
-                            cos_angle_outer: consts::LIGHT_ANGLE_OUTER.cos(),
-                            ambient: consts::AMBIENT_LIGHT_COLOR,
-                            flash_ambient: ambient_flash.into(),
-                            _padding0: Default::default(),
-                            _padding1: Default::default(),
-                            _padding2: Default::default(),
-                            _padding3: Default::default(),
-                            _padding4: Default::default()
-                        }]);
-                        map.write_attributes(&flash_instance_view, 0, &flashes);
-                        map.write_attributes(&text_vertex_view, 0, &text_vertices);
-                        map.write_indices(&text_index_view, 0, &text_indices);
-                        map.write_uniforms(&text_uniform_view, 0, &[TextBinding { aspect: width as f32 / height as f32, height: 0.1 }]);
-                    }
-                    
-                    let framebuffer = framebuffers.get(&image_index);
-                    let mut record = command_buffer.record();
-                    let mut pass = record.render_pass(&render_pass, &framebuffer);
-                    pass
-                        .bind_descriptor_sets(&pipeline_layout, &[&uniform_descriptor[0][0], &uniform_descriptor[1][0], &tex_descriptor])
-                        .bind_pipeline(&cave_pipeline);
-                    for block in blocks.borrow().values()
-                    {
-                        pass
-                            .bind_attributes(0, [AttributeBinding::from::<Vertex>(&block.buffer, &block.vertex_view)])
-                            .bind_indices(IndexBinding::from(&block.buffer, &block.index_view))
-                            .draw(DrawMode::index(block.index_view.count()));
-                    }
-                    pass
-                        .bind_pipeline(&flash_pipeline)
-                        .bind_attributes(0, [
-                            AttributeBinding::from::<flash::FlashVertex>(&dynamic_buffer, &flash_vertex_view),
-                            AttributeBinding::from::<flash::FlashInstance>(&dynamic_buffer, &flash_instance_view)
-                        ])
-                        .bind_indices(IndexBinding::from(&dynamic_buffer, &flash_index_view))
-                        .draw(DrawMode::index_instanced(flash_index_view.count(), flashes.len() as u32));
-                    pass
-                        .bind_pipeline(&bg_pipeline)
-                        .draw(DrawMode::vertex(36));
-                    pass
-                        .bind_pipeline(&text_pipeline)
-                        .bind_descriptor_sets(&text_pipeline_layout, &[&uniform_descriptor[2][0]])
-                        .bind_attributes(0, [AttributeBinding::from::<TextVertex>(&dynamic_buffer, &text_vertex_view)])
-                        .bind_indices(IndexBinding::from(&dynamic_buffer, &text_index_view))
-                        .draw(DrawMode::index(text_indices.len() as u32));
-                    drop(pass);
-                    drop(record);
-
+                    may_begin_drawing.reset();
+                    draw(command_buffer, &render_pass, &framebuffers.get(&image_index), dynamic_buffer, uniform_descriptor, &pipelines, &sim, pose, &text, (width, height));
                     let graphic_queue = graphic_queue.lock().unwrap();
                     command_buffer.submit(&graphic_queue, [&image_available], [&rendering_finished], Some(&may_begin_drawing));
-                    if shot
+                    if window_shot
                     {
-                        let image_type = ImageType { channel: Swapchain::IMAGE_CHANNEL_TYPE, width, height, layers: ImageLayers::Single };
-                        let buffer = device.new_image_buffer(image_type);
-                        let fence = command_pool.new_command_buffer().copy_from_image(&graphic_queue, CopyImageSource::Swapchain(swapchain.get_image(&image_index)), &buffer, device.new_fence(false));
-                        shot_command_buffer = Some(fence.command_buffer);
-                        let fence = fence.mark;
-                        std::thread::spawn(move ||
-                        {
-                            fence.wait();
-                            let mut image = Vec::with_capacity(buffer.size());
-                            image.resize(buffer.size(), 0);
-                            buffer.read(&mut image);
-                            let mut image = image::DynamicImage::ImageRgba8(image::ImageBuffer::from_raw(width, height, image).unwrap()).into_rgba8();
-                            for [r, _, b, _] in image.enumerate_pixels_mut().map(|(_, _, p)| &mut p.0) { std::mem::swap(r, b); }
-                            image.save_with_format("screenshot.png", image::ImageFormat::Png).unwrap();
-                            println!("screenshot saved");
-                        });
-                        shot = false;
+                        let path = encoder.next_path(&config.screenshots);
+                        let saved = encoder.capture(&command_pool, &graphic_queue, CopyImageSource::Swapchain(swapchain.get_image(&image_index)), (width, height), path, screenshot::metadata(&sim, &config));
+                        if !saved { shots_skipped += 1; }
                     }
                     swapchain.present(image_index, &graphic_queue, [rendering_finished]);
                 }
+                //supersampled screenshots render the frame again at a multiple of the window size, reusing its buffers once it is done
+                if let Some(scale) = shot.take_if(|scale| *scale > 1)
+                {
+                    let scale = scale.min(consts::MAX_CAPTURE_SIZE / width.max(height).max(1)).max(1);
+                    let size = (width * scale, height * scale);
+                    device.idle();
+                    let (framebuffer, image, _attachments) = new_offscreen(&offscreen_pass, size.0, size.1);
+                    let mut command_buffer = command_pool.new_command_buffer();
+                    draw(&mut command_buffer, &offscreen_pass, &framebuffer, dynamic_buffer, uniform_descriptor, &new_pipelines(&offscreen_pass, size.0, size.1), &sim, pose, &text, size);
+                    let rendered = device.new_fence(false);
+                    let path = encoder.next_path(&config.screenshots);
+                    let saved =
+                    {
+                        let graphic_queue = graphic_queue.lock().unwrap();
+                        command_buffer.submit(&graphic_queue, [], [], Some(&rendered));
+                        rendered.wait();
+                        encoder.capture(&command_pool, &graphic_queue, CopyImageSource::Image(&image), size, path, screenshot::metadata(&sim, &config))
+                    };
+                    if !saved { shots_skipped += 1; }
+                    //the image has to outlive the copy
+                    device.idle();
+                }
 
                 window.request_redraw();
             },
             _ => {}
         }
     }).unwrap();
-//wait for shutdown
-    for generator in generators { generator.shutdown(); }
+//wait for shutdown, quitting in the middle of a run still counts
+    if matches!(screen, menu::Screen::Playing | menu::Screen::Paused) { finish_run(&sim, recorder, player.is_none().then_some(&mut leaderboard)); }
+    print_stats(generator.shutdown());
+    if shots_skipped > 0 { println!("Skipped {shots_skipped} screenshots, the window could not be drawn to or earlier ones were still being written"); }
+    encoder.finish();
     device.idle();
 }
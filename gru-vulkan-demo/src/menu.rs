@@ -0,0 +1,54 @@
+use crate::{cave, sim, bindings::{self, Action}, consts};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Screen
+{
+    Title,
+    Playing,
+    Paused,
+    //rank of the finished run on the leaderboard, if it made it there
+    GameOver(Option<usize>)
+}
+
+//one line of a menu, centered on the screen and `row` lines below its middle
+pub struct Line
+{
+    pub text: String,
+    pub row: f32,
+    pub scale: f32
+}
+
+impl Screen
+{
+    pub fn lines<T: cave::NoiseGradient>(self, sim: &sim::Simulation<T>, controls: &bindings::Controls) -> Vec<Line>
+    {
+        let line = |text: String, row: f32, scale: f32| Line { text, row, scale };
+        let key = |action| controls.label(action);
+        match self
+        {
+            Screen::Title => vec!
+            [
+                line("CAVE JUMPER".to_owned(), -2.0, consts::MENU_TITLE_SCALE),
+                line(format!("SEED {}", sim.seed), 0.0, 1.0),
+                line(format!("{} START   {} NEW SEED   {} QUIT", key(Action::Confirm), key(Action::NewSeed), key(Action::Quit)), 1.5, 1.0)
+            ],
+            Screen::Playing => Vec::new(),
+            Screen::Paused => vec!
+            [
+                line("PAUSED".to_owned(), -2.0, consts::MENU_TITLE_SCALE),
+                line(format!("{} CONTINUE   {} QUIT", key(Action::Pause), key(Action::Quit)), 0.0, 1.0)
+            ],
+            Screen::GameOver(rank) =>
+            {
+                let mut lines = vec!
+                [
+                    line("GAME OVER".to_owned(), -2.0, consts::MENU_TITLE_SCALE),
+                    line(format!("DISTANCE {:.0}   FLASHES {}   SEED {}", sim.cam.pos.2.max(0.0), sim.flashes_collected, sim.seed), 0.0, 1.0)
+                ];
+                if let Some(rank) = rank { lines.push(line(format!("RANK {}", rank + 1), 1.5, 1.0)); }
+                lines.push(line(format!("{} RETRY   {} NEW SEED   {} QUIT", key(Action::Confirm), key(Action::NewSeed), key(Action::Quit)), 3.0, 1.0));
+                lines
+            }
+        }
+    }
+}
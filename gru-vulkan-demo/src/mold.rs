@@ -4,7 +4,7 @@ pub struct Vertex
 {
     pub position: Vec3,
     pub normal: Vec3,
-    pub coords: (f32, f32)
+    pub color: Vec3
 }
 
 pub trait Mold
@@ -13,16 +13,316 @@ pub trait Mold
     fn gradient(&self, pos: Vec3) -> Vec3;
     fn color(&self, pos: Vec3) -> Vec3;
 
+    //override when both can be computed together cheaper than one after the other
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3) { (self.value(pos), self.gradient(pos)) }
+
     fn new_vertex(&self, pos: Vec3) -> Vertex
     {
+        //texture coordinates and layers come from the position, see cave.frag
         let normal = self.gradient(pos).unit();
-        let phi = f32::atan2(normal.1, normal.0);
-        let cos_theta = normal.2;
         Vertex
         {
             position: pos,
             normal: normal * (-1.0),
-            coords: (phi * std::f32::consts::FRAC_1_PI, cos_theta)
+            color: self.color(pos)
+        }
+    }
+}
+
+//combinators and primitives to compose molds from, positive is solid for all of them
+//every one passes gradients through exactly, so meshes built from compositions keep correct normals
+
+fn dot(a: Vec3, b: Vec3) -> f32 { a.0 * b.0 + a.1 * b.1 + a.2 * b.2 }
+fn cross(a: Vec3, b: Vec3) -> Vec3 { Vec3(a.1 * b.2 - a.2 * b.1, a.2 * b.0 - a.0 * b.2, a.0 * b.1 - a.1 * b.0) }
+
+//a missing mold is empty everywhere, so optional parts can be left out of a composition
+impl<M: Mold> Mold for Option<M>
+{
+    fn value(&self, pos: Vec3) -> f32 { self.as_ref().map_or(f32::NEG_INFINITY, |mold| mold.value(pos)) }
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.as_ref().map_or(Vec3(0.0, 0.0, 0.0), |mold| mold.gradient(pos)) }
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3) { self.as_ref().map_or((f32::NEG_INFINITY, Vec3(0.0, 0.0, 0.0)), |mold| mold.value_gradient(pos)) }
+    fn color(&self, pos: Vec3) -> Vec3 { self.as_ref().map_or(Vec3(0.0, 0.0, 0.0), |mold| mold.color(pos)) }
+}
+
+pub struct Sphere(pub f32);
+
+impl Mold for Sphere
+{
+    fn value(&self, pos: Vec3) -> f32 { self.0 - pos.norm() }
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.value_gradient(pos).1 }
+
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3)
+    {
+        let norm = pos.norm();
+        (self.0 - norm, if norm == 0.0 { Vec3(0.0, 0.0, 0.0) } else { pos * (-1.0 / norm) })
+    }
+
+    fn color(&self, _: Vec3) -> Vec3 { Vec3(1.0, 1.0, 1.0) }
+}
+
+//everything within radius of the segment from a to b
+pub struct Capsule
+{
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f32
+}
+
+impl Mold for Capsule
+{
+    fn value(&self, pos: Vec3) -> f32 { self.value_gradient(pos).0 }
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.value_gradient(pos).1 }
+
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3)
+    {
+        let axis = self.b - self.a;
+        let t = (dot(pos - self.a, axis) / dot(axis, axis)).clamp(0.0, 1.0);
+        Sphere(self.radius).value_gradient(pos - (self.a + axis * t))
+    }
+
+    fn color(&self, _: Vec3) -> Vec3 { Vec3(1.0, 1.0, 1.0) }
+}
+
+#[derive(Clone, Copy)]
+pub enum Op
+{
+    Union,
+    Intersection,
+    //the second carved out of the first
+    Subtraction
+}
+
+impl Op
+{
+    //combines two values with their gradients, smoothness > 0 rounds the seam off over about that distance
+    pub fn apply(self, a: (f32, Vec3), b: (f32, Vec3), smoothness: f32) -> (f32, Vec3)
+    {
+        match self
+        {
+            Op::Union =>
+            {
+                //a side that is empty or solid everywhere decides on its own, blending two of them would give nan
+                if a.0 == f32::INFINITY || b.0 == f32::NEG_INFINITY { return a; }
+                if b.0 == f32::INFINITY || a.0 == f32::NEG_INFINITY { return b; }
+                //polynomial smooth max, its derivative by the blend factor vanishes, so the gradient is just blended
+                let h = if smoothness > 0.0 { (0.5 + 0.5 * (a.0 - b.0) / smoothness).clamp(0.0, 1.0) } else if a.0 >= b.0 { 1.0 } else { 0.0 };
+                if h == 1.0 { a } else if h == 0.0 { b } else { (b.0 + (a.0 - b.0) * h + smoothness * h * (1.0 - h), b.1 + (a.1 - b.1) * h) }
+            },
+            Op::Intersection =>
+            {
+                let (value, gradient) = Op::Union.apply((-a.0, -a.1), (-b.0, -b.1), smoothness);
+                (-value, -gradient)
+            },
+            Op::Subtraction => Op::Intersection.apply(a, (-b.0, -b.1), smoothness)
+        }
+    }
+}
+
+pub struct Combine<A: Mold, B: Mold>
+{
+    pub a: A,
+    pub b: B,
+    pub op: Op,
+    pub smoothness: f32
+}
+
+impl<A: Mold, B: Mold> Mold for Combine<A, B>
+{
+    fn value(&self, pos: Vec3) -> f32 { self.value_gradient(pos).0 }
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.value_gradient(pos).1 }
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3) { self.op.apply(self.a.value_gradient(pos), self.b.value_gradient(pos), self.smoothness) }
+
+    //the color of whichever side makes up the surface
+    fn color(&self, pos: Vec3) -> Vec3
+    {
+        let (a, b) = (self.a.value(pos), self.b.value(pos));
+        let take_a = match self.op
+        {
+            Op::Union => a >= b,
+            Op::Intersection => a <= b,
+            Op::Subtraction => a <= -b
+        };
+        if take_a { self.a.color(pos) } else { self.b.color(pos) }
+    }
+}
+
+pub struct Translate<M: Mold>
+{
+    pub mold: M,
+    pub offset: Vec3
+}
+
+impl<M: Mold> Mold for Translate<M>
+{
+    fn value(&self, pos: Vec3) -> f32 { self.mold.value(pos - self.offset) }
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.mold.gradient(pos - self.offset) }
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3) { self.mold.value_gradient(pos - self.offset) }
+    fn color(&self, pos: Vec3) -> Vec3 { self.mold.color(pos - self.offset) }
+}
+
+//scales distances along with the shape, so a distance field stays one
+pub struct Scale<M: Mold>
+{
+    pub mold: M,
+    pub factor: f32
+}
+
+impl<M: Mold> Mold for Scale<M>
+{
+    fn value(&self, pos: Vec3) -> f32 { self.mold.value(pos * (1.0 / self.factor)) * self.factor }
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.mold.gradient(pos * (1.0 / self.factor)) }
+
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3)
+    {
+        let (value, gradient) = self.mold.value_gradient(pos * (1.0 / self.factor));
+        (value * self.factor, gradient)
+    }
+
+    fn color(&self, pos: Vec3) -> Vec3 { self.mold.color(pos * (1.0 / self.factor)) }
+}
+
+//by angle around the unit axis, right handed
+pub struct Rotate<M: Mold>
+{
+    pub mold: M,
+    axis: Vec3,
+    cos: f32,
+    sin: f32
+}
+
+impl<M: Mold> Rotate<M>
+{
+    pub fn new(mold: M, axis: Vec3, angle: f32) -> Self { Self { mold, axis: axis.unit(), cos: angle.cos(), sin: angle.sin() } }
+
+    //rodrigues' formula
+    fn rotate(&self, v: Vec3, sin: f32) -> Vec3 { v * self.cos + cross(self.axis, v) * sin + self.axis * (dot(self.axis, v) * (1.0 - self.cos)) }
+}
+
+impl<M: Mold> Mold for Rotate<M>
+{
+    fn value(&self, pos: Vec3) -> f32 { self.mold.value(self.rotate(pos, -self.sin)) }
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.rotate(self.mold.gradient(self.rotate(pos, -self.sin)), self.sin) }
+
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3)
+    {
+        let (value, gradient) = self.mold.value_gradient(self.rotate(pos, -self.sin));
+        (value, self.rotate(gradient, self.sin))
+    }
+
+    fn color(&self, pos: Vec3) -> Vec3 { self.mold.color(self.rotate(pos, -self.sin)) }
+}
+
+//samples the mold at pos + displacement(pos), the field also returns the gradients of the three displacement components
+pub struct Warp<M: Mold, F: Fn(Vec3) -> (Vec3, [Vec3; 3])>
+{
+    pub mold: M,
+    pub field: F
+}
+
+impl<M: Mold, F: Fn(Vec3) -> (Vec3, [Vec3; 3])> Mold for Warp<M, F>
+{
+    fn value(&self, pos: Vec3) -> f32 { self.mold.value(pos + (self.field)(pos).0) }
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.value_gradient(pos).1 }
+
+    //chain rule, the transposed jacobian of the displaced position applied to the mold's gradient
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3)
+    {
+        let (displacement, [dx, dy, dz]) = (self.field)(pos);
+        let (value, gradient) = self.mold.value_gradient(pos + displacement);
+        (value, gradient + dx * gradient.0 + dy * gradient.1 + dz * gradient.2)
+    }
+
+    fn color(&self, pos: Vec3) -> Vec3 { self.mold.color(pos + (self.field)(pos).0) }
+}
+
+//copies of the mold every period along each axis, centered on the origin, 0 leaves an axis alone
+pub struct Repeat<M: Mold>
+{
+    pub mold: M,
+    pub period: Vec3
+}
+
+impl<M: Mold> Repeat<M>
+{
+    fn fold(&self, pos: Vec3) -> Vec3
+    {
+        let fold = |x: f32, period: f32| if period > 0.0 { x - period * (x / period).round() } else { x };
+        Vec3(fold(pos.0, self.period.0), fold(pos.1, self.period.1), fold(pos.2, self.period.2))
+    }
+}
+
+impl<M: Mold> Mold for Repeat<M>
+{
+    fn value(&self, pos: Vec3) -> f32 { self.mold.value(self.fold(pos)) }
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.mold.gradient(self.fold(pos)) }
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3) { self.mold.value_gradient(self.fold(pos)) }
+    fn color(&self, pos: Vec3) -> Vec3 { self.mold.color(self.fold(pos)) }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn numeric_gradient(mold: &dyn Mold, pos: Vec3) -> Vec3
+    {
+        let epsilon = 1e-3;
+        let difference = |axis: Vec3| (mold.value(pos + axis * epsilon) - mold.value(pos - axis * epsilon)) / (2.0 * epsilon);
+        Vec3(difference(Vec3(1.0, 0.0, 0.0)), difference(Vec3(0.0, 1.0, 0.0)), difference(Vec3(0.0, 0.0, 1.0)))
+    }
+
+    #[test]
+    fn combinator_gradients()
+    {
+        let capsule = || Capsule { a: Vec3(-3.0, 0.0, 0.0), b: Vec3(3.0, 1.0, 0.0), radius: 1.5 };
+        let field = |pos: Vec3| (Vec3(2.0 * (pos.2 * 0.3).sin(), 0.0, 0.0), [Vec3(0.0, 0.0, 0.6 * (pos.2 * 0.3).cos()), Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0)]);
+        let molds: Vec<(&str, Box<dyn Mold>)> = vec!
+        [
+            ("sphere", Box::new(Sphere(4.0))),
+            ("capsule", Box::new(capsule())),
+            ("union", Box::new(Combine { a: Sphere(4.0), b: capsule(), op: Op::Union, smoothness: 1.0 })),
+            ("intersection", Box::new(Combine { a: Sphere(4.0), b: capsule(), op: Op::Intersection, smoothness: 1.0 })),
+            ("subtraction", Box::new(Combine { a: Sphere(4.0), b: capsule(), op: Op::Subtraction, smoothness: 1.0 })),
+            ("translate", Box::new(Translate { mold: capsule(), offset: Vec3(1.0, -2.0, 0.5) })),
+            ("scale", Box::new(Scale { mold: capsule(), factor: 2.5 })),
+            ("rotate", Box::new(Rotate::new(capsule(), Vec3(1.0, 2.0, -0.5), 0.7))),
+            ("warp", Box::new(Warp { mold: capsule(), field })),
+            ("repeat", Box::new(Repeat { mold: Sphere(2.0), period: Vec3(0.0, 7.0, 5.0) }))
+        ];
+        for (name, mold) in &molds
+        {
+            let mut kinks = 0;
+            for i in 0..1000
+            {
+                let pos = Vec3(((i * 37) % 61) as f32 * 0.2 - 6.0, ((i * 53) % 41) as f32 * 0.3 - 6.0, ((i * 17) % 97) as f32 * 0.15 - 7.0);
+                let (value, gradient) = mold.value_gradient(pos);
+                assert!((value - mold.value(pos)).abs() < 1e-5, "{name}");
+                assert!((gradient - mold.gradient(pos)).norm() < 1e-5, "{name}");
+                if (numeric_gradient(mold.as_ref(), pos) - gradient).norm() > 0.01 { kinks += 1; }
+            }
+            //the sharp parts of the primitives and the seams of repeat are not differentiable
+            assert!(kinks < 20, "{name}: {kinks} gradients off");
+        }
+    }
+
+    #[test]
+    fn empty_parts()
+    {
+        let pos = Vec3(1.0, 2.0, 0.5);
+        let sphere = Sphere(3.0).value_gradient(pos);
+        for op in [Op::Union, Op::Intersection, Op::Subtraction]
+        {
+            let both = Combine { a: None::<Sphere>, b: None::<Sphere>, op, smoothness: 1.0 }.value_gradient(pos);
+            assert_eq!(both.0, f32::NEG_INFINITY);
+            assert!(!both.1.0.is_nan() && !both.1.1.is_nan() && !both.1.2.is_nan());
+        }
+        let union = Combine { a: Some(Sphere(3.0)), b: None::<Sphere>, op: Op::Union, smoothness: 1.0 }.value_gradient(pos);
+        let subtraction = Combine { a: Sphere(3.0), b: None::<Sphere>, op: Op::Subtraction, smoothness: 1.0 }.value_gradient(pos);
+        for (value, gradient) in [union, subtraction]
+        {
+            assert_eq!(value, sphere.0);
+            assert!((gradient - sphere.1).norm() == 0.0);
         }
+        assert_eq!(Combine { a: Sphere(3.0), b: None::<Sphere>, op: Op::Intersection, smoothness: 1.0 }.value(pos), f32::NEG_INFINITY);
     }
 }
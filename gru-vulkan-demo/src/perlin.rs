@@ -0,0 +1,206 @@
+//Perlin based noise that also returns its gradient.
+//Reproduces noise 0.7 (Perlin, Billow, Fbm) bit for bit, so switching to it does not change any cave.
+
+const TABLE_SIZE: usize = 256;
+const MAX_OCTAVES: usize = 32;
+
+const GRADIENTS_2: [[f64; 2]; 4] = [[1.0, 1.0], [-1.0, 1.0], [1.0, -1.0], [-1.0, -1.0]];
+const GRADIENTS_3: [[f64; 3]; 16] =
+[
+    [ 1.0,  1.0,  0.0], [-1.0,  1.0,  0.0], [ 1.0, -1.0,  0.0], [-1.0, -1.0,  0.0],
+    [ 1.0,  0.0,  1.0], [-1.0,  0.0,  1.0], [ 1.0,  0.0, -1.0], [-1.0,  0.0, -1.0],
+    [ 0.0,  1.0,  1.0], [ 0.0, -1.0,  1.0], [ 0.0,  1.0, -1.0], [ 0.0, -1.0, -1.0],
+    [ 1.0,  1.0,  0.0], [-1.0,  1.0,  0.0], [ 0.0, -1.0,  1.0], [ 0.0, -1.0, -1.0]
+];
+
+fn s_curve5(t: f64) -> f64 { t * t * t * (t * (t * 6.0 - 15.0) + 10.0) }
+fn s_curve5_derivative(t: f64) -> f64 { 30.0 * t * t * (t - 1.0) * (t - 1.0) }
+
+#[derive(Clone)]
+pub struct Perlin
+{
+    perm: [u8; TABLE_SIZE]
+}
+
+impl Perlin
+{
+    //same permutation table as noise::Perlin::new().set_seed(seed): a Fisher-Yates shuffle driven by XorShift
+    pub fn new(seed: u32) -> Self
+    {
+        let mut state = [1, seed, seed, seed];
+        let mut next_u32 = ||
+        {
+            let t = state[0] ^ (state[0] << 11);
+            state = [state[1], state[2], state[3], state[3] ^ (state[3] >> 19) ^ (t ^ (t >> 8))];
+            state[3]
+        };
+        let mut perm = [0; TABLE_SIZE];
+        for (i, value) in perm.iter_mut().enumerate() { *value = i as u8; }
+        for i in (1..TABLE_SIZE).rev()
+        {
+            let range = i as u64 + 1;
+            let zone = ((range << (range as u32).leading_zeros()) as u32).wrapping_sub(1);
+            let j = loop
+            {
+                let product = next_u32() as u64 * range;
+                if product as u32 <= zone { break (product >> 32) as usize; }
+            };
+            perm.swap(i, j);
+        }
+        Self { perm }
+    }
+
+    fn hash2(&self, x: isize, y: isize) -> usize { self.perm[self.perm[(x & 0xff) as usize] as usize ^ (y & 0xff) as usize] as usize }
+    fn hash3(&self, x: isize, y: isize, z: isize) -> usize { self.perm[self.hash2(x, y) ^ (z & 0xff) as usize] as usize }
+
+    pub fn get2(&self, [x, y]: [f64; 2]) -> (f64, [f64; 2])
+    {
+        let scale = 2.0_f64.sqrt();
+        let (fx, fy) = (x.floor(), y.floor());
+        let (ix, iy) = (fx as isize, fy as isize);
+        let (dx, dy) = (x - fx, y - fy);
+        let corner = |cx: isize, cy: isize, ox: f64, oy: f64|
+        {
+            let g = GRADIENTS_2[self.hash2(ix + cx, iy + cy) & 0b11];
+            (g[0] * ox + g[1] * oy, g)
+        };
+        let (g00, n00) = corner(0, 0, dx, dy);
+        let (g10, n10) = corner(1, 0, dx - 1.0, dy);
+        let (g01, n01) = corner(0, 1, dx, dy - 1.0);
+        let (g11, n11) = corner(1, 1, dx - 1.0, dy - 1.0);
+
+        let (u, v) = (s_curve5(dx), s_curve5(dy));
+        let (du, dv) = (s_curve5_derivative(dx), s_curve5_derivative(dy));
+        let k0 = g00;
+        let k1 = g10 - g00;
+        let k2 = g01 - g00;
+        let k3 = g00 + g11 - g10 - g01;
+        let value = (k0 + k1 * u + k2 * v + k3 * u * v) * scale;
+        if !(-1.0..=1.0).contains(&value) { return (value.clamp(-1.0, 1.0), [0.0; 2]); }
+
+        let mut gradient = [0.0; 2];
+        for (axis, gradient) in gradient.iter_mut().enumerate()
+        {
+            let (dk0, dk1, dk2, dk3) = (n00[axis], n10[axis] - n00[axis], n01[axis] - n00[axis], n00[axis] + n11[axis] - n10[axis] - n01[axis]);
+            let (du, dv) = if axis == 0 { (du, 0.0) } else { (0.0, dv) };
+            *gradient = (dk0 + dk1 * u + k1 * du + dk2 * v + k2 * dv + dk3 * u * v + k3 * (du * v + u * dv)) * scale;
+        }
+        (value, gradient)
+    }
+
+    pub fn get3(&self, point: [f64; 3]) -> (f64, [f64; 3]) { self.sample3::<true>(point) }
+
+    //same value as get3, for the marching cubes which have no use for the gradient
+    pub fn value3(&self, point: [f64; 3]) -> f64 { self.sample3::<false>(point).0 }
+
+    fn sample3<const GRADIENT: bool>(&self, [x, y, z]: [f64; 3]) -> (f64, [f64; 3])
+    {
+        let scale = 2.0 / 3.0_f64.sqrt();
+        let (fx, fy, fz) = (x.floor(), y.floor(), z.floor());
+        let (ix, iy, iz) = (fx as isize, fy as isize, fz as isize);
+        let (dx, dy, dz) = (x - fx, y - fy, z - fz);
+        let corner = |cx: isize, cy: isize, cz: isize|
+        {
+            let g = GRADIENTS_3[self.hash3(ix + cx, iy + cy, iz + cz) & 0b1111];
+            let (ox, oy, oz) = (dx - cx as f64, dy - cy as f64, dz - cz as f64);
+            (g[0] * ox + g[1] * oy + g[2] * oz, g)
+        };
+        let (g000, n000) = corner(0, 0, 0);
+        let (g100, n100) = corner(1, 0, 0);
+        let (g010, n010) = corner(0, 1, 0);
+        let (g110, n110) = corner(1, 1, 0);
+        let (g001, n001) = corner(0, 0, 1);
+        let (g101, n101) = corner(1, 0, 1);
+        let (g011, n011) = corner(0, 1, 1);
+        let (g111, n111) = corner(1, 1, 1);
+
+        let (a, b, c) = (s_curve5(dx), s_curve5(dy), s_curve5(dz));
+        let (da, db, dc) = (s_curve5_derivative(dx), s_curve5_derivative(dy), s_curve5_derivative(dz));
+        let coefficients = |g000: f64, g100: f64, g010: f64, g110: f64, g001: f64, g101: f64, g011: f64, g111: f64|
+        [
+            g000,
+            g100 - g000,
+            g010 - g000,
+            g001 - g000,
+            g000 + g110 - g100 - g010,
+            g000 + g101 - g100 - g001,
+            g000 + g011 - g010 - g001,
+            g100 + g010 + g001 + g111 - g000 - g110 - g101 - g011
+        ];
+        let k = coefficients(g000, g100, g010, g110, g001, g101, g011, g111);
+        let value = (k[0] + k[1] * a + k[2] * b + k[3] * c + k[4] * a * b + k[5] * a * c + k[6] * b * c + k[7] * a * b * c) * scale;
+        if !GRADIENT || !(-1.0..=1.0).contains(&value) { return (value.clamp(-1.0, 1.0), [0.0; 3]); }
+
+        let mut gradient = [0.0; 3];
+        for (axis, gradient) in gradient.iter_mut().enumerate()
+        {
+            let dk = coefficients(n000[axis], n100[axis], n010[axis], n110[axis], n001[axis], n101[axis], n011[axis], n111[axis]);
+            let (da, db, dc) = match axis { 0 => (da, 0.0, 0.0), 1 => (0.0, db, 0.0), _ => (0.0, 0.0, dc) };
+            *gradient = (dk[0] + dk[1] * a + dk[2] * b + dk[3] * c + dk[4] * a * b + dk[5] * a * c + dk[6] * b * c + dk[7] * a * b * c
+                + k[1] * da + k[2] * db + k[3] * dc + k[4] * (da * b + a * db) + k[5] * (da * c + a * dc) + k[6] * (db * c + b * dc)
+                + k[7] * (da * b * c + a * db * c + a * b * dc)) * scale;
+        }
+        (value, gradient)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FractalKind
+{
+    Billow,
+    Fbm
+}
+
+#[derive(Clone)]
+pub struct Fractal
+{
+    pub kind: FractalKind,
+    sources: Vec<Perlin>,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub persistence: f64
+}
+
+impl Fractal
+{
+    pub fn new(kind: FractalKind, seed: u32, octaves: usize, frequency: f64, lacunarity: f64, persistence: f64) -> Self
+    {
+        let sources = (0..octaves.clamp(1, MAX_OCTAVES) as u32).map(|octave| Perlin::new(seed.wrapping_add(octave))).collect();
+        Self { kind, sources, frequency, lacunarity, persistence }
+    }
+
+    pub fn get(&self, point: [f64; 3]) -> (f64, [f64; 3]) { self.sample::<true>(point) }
+
+    pub fn value(&self, point: [f64; 3]) -> f64 { self.sample::<false>(point).0 }
+
+    fn sample<const GRADIENT: bool>(&self, point: [f64; 3]) -> (f64, [f64; 3])
+    {
+        let mut point = point.map(|x| x * self.frequency);
+        let mut scale = self.frequency;
+        let mut value = 0.0;
+        let mut gradient = [0.0; 3];
+        for (octave, source) in self.sources.iter().enumerate()
+        {
+            let amplitude = self.persistence.powi(octave as i32);
+            let (signal, signal_gradient) = source.sample3::<GRADIENT>(point);
+            let (signal, factor) = match self.kind
+            {
+                FractalKind::Billow => (signal.abs().mul_add(2.0, -1.0), 2.0 * signal.signum()),
+                FractalKind::Fbm => (signal, 1.0)
+            };
+            value += signal * amplitude;
+            if GRADIENT { for axis in 0..3 { gradient[axis] += signal_gradient[axis] * factor * amplitude * scale; } }
+            point = point.map(|x| x * self.lacunarity);
+            scale *= self.lacunarity;
+        }
+        match self.kind
+        {
+            FractalKind::Billow => (value * 0.5, gradient.map(|x| x * 0.5)),
+            FractalKind::Fbm =>
+            {
+                let normalization = 2.0 - self.persistence.powi(self.sources.len() as i32 - 1);
+                (value / normalization, gradient.map(|x| x / normalization))
+            }
+        }
+    }
+}
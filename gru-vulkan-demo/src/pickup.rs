@@ -0,0 +1,136 @@
+use gru_misc::math::*;
+use crate::{mold::{self, Mold}, flash, consts};
+
+#[derive(Clone, Copy)]
+pub enum Shape
+{
+    Flash,
+    Sphere(f32),
+    Octahedron(f32),
+    Cube(f32)
+}
+
+impl Shape
+{
+    //half extents of the box the mesh is built in
+    pub fn bounds(self) -> Vec3
+    {
+        match self
+        {
+            Shape::Flash => Vec3(consts::FLASH_HEIGHT, consts::FLASH_RADIUS, consts::FLASH_RADIUS),
+            Shape::Sphere(r) | Shape::Octahedron(r) | Shape::Cube(r) => Vec3(r, r, r)
+        }
+    }
+}
+
+impl mold::Mold for Shape
+{
+    fn value(&self, pos: Vec3) -> f32 { self.value_gradient(pos).0 }
+
+    fn gradient(&self, pos: Vec3) -> Vec3 { self.value_gradient(pos).1 }
+
+    fn value_gradient(&self, pos: Vec3) -> (f32, Vec3)
+    {
+        match *self
+        {
+            Shape::Flash => flash::FlashMold.value_gradient(pos),
+            Shape::Sphere(r) =>
+            {
+                let norm = pos.norm();
+                (r - norm, if norm == 0.0 { Vec3(0.0, 0.0, 0.0) } else { pos * (-1.0 / norm) })
+            },
+            Shape::Octahedron(r) => (r - pos.0.abs() - pos.1.abs() - pos.2.abs(), Vec3(-pos.0.signum(), -pos.1.signum(), -pos.2.signum())),
+            Shape::Cube(r) =>
+            {
+                let (x, y, z) = (pos.0.abs(), pos.1.abs(), pos.2.abs());
+                if x >= y && x >= z { (r - x, Vec3(-pos.0.signum(), 0.0, 0.0)) }
+                else if y >= z { (r - y, Vec3(0.0, -pos.1.signum(), 0.0)) }
+                else { (r - z, Vec3(0.0, 0.0, -pos.2.signum())) }
+            }
+        }
+    }
+
+    fn color(&self, pos: Vec3) -> Vec3 { flash::FlashMold.color(pos) }
+}
+
+#[derive(Clone, Copy)]
+pub enum Effect
+{
+    //instant, turns back the chasing bias by flash_power
+    Time,
+    Jump(f32),
+    Gravity(f32),
+    //instant, recharges the flashlight
+    Battery(f32),
+    //slows down the chasing bias
+    Bias(f32)
+}
+
+pub struct Kind
+{
+    pub name: &'static str,
+    pub shape: Shape,
+    //None picks a color from the position
+    pub color: Option<(f32, f32, f32)>,
+    pub duration: f32,
+    pub probability: f32,
+    pub effect: Effect
+}
+
+pub const KINDS: [Kind; 5] =
+[
+    Kind { name: "flash", shape: Shape::Flash, color: None, duration: 0.0, probability: consts::FLASH_BLOCK_PROB, effect: Effect::Time },
+    Kind { name: "jump", shape: Shape::Octahedron(0.25), color: Some((0.2, 1.0, 0.3)), duration: 10.0, probability: 0.05, effect: Effect::Jump(1.6) },
+    Kind { name: "gravity", shape: Shape::Sphere(0.2), color: Some((0.6, 0.3, 1.0)), duration: 8.0, probability: 0.05, effect: Effect::Gravity(0.4) },
+    Kind { name: "battery", shape: Shape::Cube(0.15), color: Some(consts::BATTERY_COLOR), duration: 0.0, probability: 0.05, effect: Effect::Battery(1.0) },
+    Kind { name: "slow", shape: Shape::Octahedron(0.3), color: Some((1.0, 0.3, 0.2)), duration: 6.0, probability: 0.03, effect: Effect::Bias(0.5) }
+];
+
+pub fn probabilities() -> [f32; KINDS.len()] { KINDS.map(|kind| kind.probability) }
+
+pub fn from_name(name: &str) -> Option<usize> { KINDS.iter().position(|kind| kind.name == name) }
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Pickup
+{
+    pub kind: usize,
+    pub pos: Vec3,
+    pub color: Vec3
+}
+
+impl Pickup
+{
+    pub fn new(kind: usize, pos: Vec3) -> Self
+    {
+        let color = KINDS[kind].color.map_or_else(|| KINDS[kind].shape.color(pos), Vec3::from);
+        Self { kind, pos, color }
+    }
+}
+
+//effects currently running, as multipliers for the camera and the simulation
+#[derive(Clone, Copy)]
+pub struct Modifiers
+{
+    pub jump: f32,
+    pub gravity: f32,
+    pub bias: f32
+}
+
+impl Modifiers
+{
+    pub fn new(active: &[(usize, f32)]) -> Self
+    {
+        let mut modifiers = Modifiers { jump: 1.0, gravity: 1.0, bias: 1.0 };
+        for (kind, _) in active
+        {
+            match KINDS[*kind].effect
+            {
+                Effect::Time | Effect::Battery(_) => {},
+                Effect::Jump(scale) => modifiers.jump *= scale,
+                Effect::Gravity(scale) => modifiers.gravity *= scale,
+                Effect::Bias(scale) => modifiers.bias *= scale
+            }
+        }
+        modifiers
+    }
+}
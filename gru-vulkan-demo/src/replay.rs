@@ -0,0 +1,243 @@
+use std::{fs::File, io::{Read, Write, BufReader, BufWriter}};
+use crate::{config, pickup, tunables, sim};
+
+const MAGIC: &[u8; 4] = b"CJRP";
+//bumped with every change to the format or to the simulation, older replays would no longer play back the same
+const VERSION: u8 = 9;
+const REPLAY_DIR: &str = "replays";
+
+const FORWARD: u16 = 1 << 0;
+const BACKWARD: u16 = 1 << 1;
+const LEFT: u16 = 1 << 2;
+const RIGHT: u16 = 1 << 3;
+const JUMP: u16 = 1 << 4;
+const NOCLIP_ON: u16 = 1 << 5;
+const NOCLIP_OFF: u16 = 1 << 6;
+const LOOK: u16 = 1 << 7;
+const TOGGLE_LIGHT: u16 = 1 << 8;
+const GRAPPLE: u16 = 1 << 9;
+const REEL: u16 = 1 << 10;
+
+/* file layout (little endian)
+    header: magic, version, seed: u32, noise: u8, octaves: u32, frequency: f64, lacunarity: f64, persistence: f64,
+        pickup kinds: u8, per kind probability: f32,
+        tunables: u8, per tunable key length: u8, key, value length: u8, value as text
+    per frame: dt: f32, flags: u16, (look: f32, f32 if LOOK is set)
+*/
+
+pub struct Recorder
+{
+    file: BufWriter<File>,
+    pub path: String
+}
+
+impl Recorder
+{
+    //without explicit path every run ends up in replays/
+    pub fn create(path: Option<&str>, config: &config::Config) -> Self
+    {
+        let path = match path
+        {
+            Some(path) => path.to_owned(),
+            None =>
+            {
+                std::fs::create_dir_all(REPLAY_DIR).unwrap();
+                let now = std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH).unwrap().as_secs();
+                format!("{REPLAY_DIR}/{now}-{}.replay", config.seed)
+            }
+        };
+        let mut file = BufWriter::new(File::create(&path).unwrap_or_else(|err| panic!("{path}: {err}")));
+        let noise = config::NoiseKind::ALL.iter().position(|kind| *kind == config.noise).unwrap() as u8;
+        file.write_all(MAGIC).unwrap();
+        file.write_all(&[VERSION]).unwrap();
+        file.write_all(&config.seed.to_le_bytes()).unwrap();
+        file.write_all(&[noise]).unwrap();
+        file.write_all(&(config.octaves as u32).to_le_bytes()).unwrap();
+        file.write_all(&config.frequency.to_le_bytes()).unwrap();
+        file.write_all(&config.lacunarity.to_le_bytes()).unwrap();
+        file.write_all(&config.persistence.to_le_bytes()).unwrap();
+        file.write_all(&[config.probabilities.len() as u8]).unwrap();
+        for probability in config.probabilities { file.write_all(&probability.to_le_bytes()).unwrap(); }
+        let tunables = config.tunables.entries();
+        file.write_all(&[tunables.len() as u8]).unwrap();
+        for (key, value) in tunables
+        {
+            for text in [key, value.as_str()]
+            {
+                file.write_all(&[text.len() as u8]).unwrap();
+                file.write_all(text.as_bytes()).unwrap();
+            }
+        }
+        Self { file, path }
+    }
+
+    pub fn frame(&mut self, dt: f32, input: &sim::Input)
+    {
+        let mut flags = 0;
+        if input.forward { flags |= FORWARD; }
+        if input.backward { flags |= BACKWARD; }
+        if input.left { flags |= LEFT; }
+        if input.right { flags |= RIGHT; }
+        if input.jump { flags |= JUMP; }
+        if input.toggle_light { flags |= TOGGLE_LIGHT; }
+        if input.grapple { flags |= GRAPPLE; }
+        if input.reel { flags |= REEL; }
+        match input.noclip
+        {
+            Some(true) => flags |= NOCLIP_ON,
+            Some(false) => flags |= NOCLIP_OFF,
+            None => {}
+        }
+        if input.look != (0.0, 0.0) { flags |= LOOK; }
+        self.file.write_all(&dt.to_le_bytes()).unwrap();
+        self.file.write_all(&flags.to_le_bytes()).unwrap();
+        if flags & LOOK != 0
+        {
+            self.file.write_all(&input.look.0.to_le_bytes()).unwrap();
+            self.file.write_all(&input.look.1.to_le_bytes()).unwrap();
+        }
+    }
+
+    pub fn finish(mut self) -> String
+    {
+        self.file.flush().unwrap();
+        self.path
+    }
+}
+
+pub struct Player
+{
+    frames: std::vec::IntoIter<(f32, sim::Input)>
+}
+
+impl Player
+{
+    //overwrites the cave part of the config with the recorded one
+    pub fn open(path: &str, config: &mut config::Config) -> Self
+    {
+        let mut bytes = Vec::new();
+        BufReader::new(File::open(path).unwrap_or_else(|err| panic!("{path}: {err}"))).read_to_end(&mut bytes).unwrap();
+        let mut reader = Reader { bytes: &bytes, path };
+        if reader.take::<4>() != *MAGIC { panic!("{path}: not a replay file"); }
+        let [version] = reader.take();
+        if version != VERSION { panic!("{path}: replay version {version} can not be played back by version {VERSION}"); }
+        config.seed = u32::from_le_bytes(reader.take());
+        let [noise] = reader.take();
+        config.noise = *config::NoiseKind::ALL.get(noise as usize).unwrap_or_else(|| panic!("{path}: unknown noise {noise}"));
+        config.octaves = u32::from_le_bytes(reader.take()) as usize;
+        config.frequency = f64::from_le_bytes(reader.take());
+        config.lacunarity = f64::from_le_bytes(reader.take());
+        config.persistence = f64::from_le_bytes(reader.take());
+        config.probabilities = pickup::probabilities();
+        let [kinds] = reader.take();
+        for kind in 0..kinds as usize
+        {
+            let probability = f32::from_le_bytes(reader.take());
+            if let Some(slot) = config.probabilities.get_mut(kind) { *slot = probability; }
+        }
+        config.tunables = tunables::Tunables::new();
+        let [count] = reader.take();
+        for _ in 0..count
+        {
+            let (key, value) = (reader.text(), reader.text());
+            config.tunables.set(&key, &value).unwrap_or_else(|err| panic!("{path}: {err}"));
+        }
+        config.tunables.check().unwrap_or_else(|err| panic!("{path}: {err}"));
+
+        let mut frames = Vec::new();
+        while !reader.bytes.is_empty()
+        {
+            let dt = f32::from_le_bytes(reader.take());
+            let flags = u16::from_le_bytes(reader.take());
+            let look = if flags & LOOK != 0 { (f32::from_le_bytes(reader.take()), f32::from_le_bytes(reader.take())) } else { (0.0, 0.0) };
+            let noclip = if flags & NOCLIP_ON != 0 { Some(true) } else if flags & NOCLIP_OFF != 0 { Some(false) } else { None };
+            frames.push((dt, sim::Input
+            {
+                forward: flags & FORWARD != 0,
+                backward: flags & BACKWARD != 0,
+                left: flags & LEFT != 0,
+                right: flags & RIGHT != 0,
+                jump: flags & JUMP != 0,
+                grapple: flags & GRAPPLE != 0,
+                reel: flags & REEL != 0,
+                toggle_light: flags & TOGGLE_LIGHT != 0,
+                noclip,
+                look
+            }));
+        }
+        Self { frames: frames.into_iter() }
+    }
+}
+
+impl Iterator for Player
+{
+    type Item = (f32, sim::Input);
+
+    fn next(&mut self) -> Option<Self::Item> { self.frames.next() }
+}
+
+struct Reader<'a>
+{
+    bytes: &'a [u8],
+    path: &'a str
+}
+
+impl Reader<'_>
+{
+    fn take<const N: usize>(&mut self) -> [u8; N]
+    {
+        let (head, tail) = self.bytes.split_first_chunk().unwrap_or_else(|| panic!("{}: replay file is truncated", self.path));
+        self.bytes = tail;
+        *head
+    }
+
+    fn text(&mut self) -> String
+    {
+        let [len] = self.take();
+        let (head, tail) = self.bytes.split_at_checked(len as usize).unwrap_or_else(|| panic!("{}: replay file is truncated", self.path));
+        self.bytes = tail;
+        String::from_utf8(head.to_vec()).unwrap_or_else(|_| panic!("{}: broken replay file", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn temp_path(name: &str) -> String { std::env::temp_dir().join(name).to_str().unwrap().to_owned() }
+
+    #[test]
+    fn roundtrip()
+    {
+        let mut config = config::Config::new();
+        config.seed = 7;
+        let path = temp_path("cave-jumper-roundtrip.replay");
+        let mut recorder = Recorder::create(Some(&path), &config);
+        let mut input = sim::Input { forward: true, look: (3.0, -1.5), ..Default::default() };
+        recorder.frame(0.016, &input);
+        input.clear_impulses();
+        input.noclip = Some(false);
+        recorder.frame(0.017, &input);
+        recorder.finish();
+
+        let mut played = config::Config::new();
+        let frames: Vec<_> = Player::open(&path, &mut played).collect();
+        assert_eq!(played.seed, 7);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].1.look, (3.0, -1.5));
+        assert!(frames[1].1.forward && frames[1].1.noclip == Some(false));
+    }
+
+    #[test]
+    #[should_panic(expected = "replay version")]
+    fn old_version_rejected()
+    {
+        let path = temp_path("cave-jumper-old.replay");
+        Recorder::create(Some(&path), &config::Config::new()).finish();
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[MAGIC.len()] = VERSION - 1;
+        std::fs::write(&path, bytes).unwrap();
+        Player::open(&path, &mut config::Config::new());
+    }
+}
@@ -0,0 +1,135 @@
+use std::io::Write;
+use crate::consts;
+
+const SCORES_PATH: &str = "scores.txt";
+const HEADER: &str = "# distance score time flashes seed date";
+
+pub struct Entry
+{
+    pub distance: f32,
+    pub score: f32,
+    pub time: f32,
+    pub flashes: u32,
+    pub seed: u32,
+    pub date: u64
+}
+
+impl Entry
+{
+    //runs are ranked by how far the player got into the cave, the score breaks ties
+    fn beats(&self, other: &Self) -> bool { self.distance > other.distance || (self.distance == other.distance && self.score > other.score) }
+
+    fn parse(line: &str) -> Option<Self>
+    {
+        let mut fields = line.split_whitespace();
+        Some(Entry
+        {
+            distance: fields.next()?.parse().ok()?,
+            score: fields.next()?.parse().ok()?,
+            time: fields.next()?.parse().ok()?,
+            flashes: fields.next()?.parse().ok()?,
+            seed: fields.next()?.parse().ok()?,
+            date: fields.next()?.parse().ok()?
+        })
+    }
+}
+
+pub struct Leaderboard
+{
+    entries: Vec<Entry>,
+    //lines that did not parse are written back as they were instead of being lost on the next save
+    unparsed: Vec<String>
+}
+
+impl Leaderboard
+{
+    pub fn load() -> Self { Self::parse(&std::fs::read_to_string(SCORES_PATH).unwrap_or_default()) }
+
+    fn parse(text: &str) -> Self
+    {
+        let mut board = Self { entries: Vec::new(), unparsed: Vec::new() };
+        for line in text.lines().filter(|line| *line != HEADER)
+        {
+            match Entry::parse(line)
+            {
+                Some(entry) => board.entries.push(entry),
+                None =>
+                {
+                    if !line.starts_with('#') { eprintln!("{SCORES_PATH}: keeping unreadable line {line:?}"); }
+                    board.unparsed.push(line.to_owned());
+                }
+            }
+        }
+        board
+    }
+
+    pub fn save(&self) { self.write(&mut std::io::BufWriter::new(std::fs::File::create(SCORES_PATH).unwrap())); }
+
+    fn write(&self, file: &mut impl Write)
+    {
+        writeln!(file, "{HEADER}").unwrap();
+        for entry in &self.entries
+        {
+            writeln!(file, "{} {} {} {} {} {}", entry.distance, entry.score, entry.time, entry.flashes, entry.seed, entry.date).unwrap();
+        }
+        for line in &self.unparsed { writeln!(file, "{line}").unwrap(); }
+    }
+
+    pub fn best(&self) -> Option<&Entry> { self.entries.first() }
+
+    //returns the rank of the new entry if it made it onto the board
+    pub fn insert(&mut self, entry: Entry) -> Option<usize>
+    {
+        let rank = self.entries.iter().position(|other| entry.beats(other)).unwrap_or(self.entries.len());
+        self.entries.insert(rank, entry);
+        self.entries.truncate(consts::LEADERBOARD_SIZE);
+        if rank < self.entries.len() { Some(rank) } else { None }
+    }
+
+    pub fn print(&self, highlight: Option<usize>)
+    {
+        println!("     distance  score    time  flashes        seed  date");
+        for (rank, entry) in self.entries.iter().enumerate()
+        {
+            let marker = if highlight == Some(rank) { '>' } else { ' ' };
+            println!("{marker}{:>3}. {:>8.1} {:>6.0} {:>7.1} {:>8} {:>11}  {}", rank + 1, entry.distance, entry.score, entry.time, entry.flashes, entry.seed, date(entry.date));
+        }
+    }
+}
+
+pub fn now() -> u64 { std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH).unwrap().as_secs() }
+
+//unix seconds to YYYY-MM-DD (days to civil algorithm by Howard Hinnant)
+pub fn date(secs: u64) -> String
+{
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn unparsed_lines_kept()
+    {
+        let text = format!("{HEADER}\n120.5 300 42 3 7 1700000000\n# a note\n80 abc 10 1 7 1700000000\n");
+        let mut board = Leaderboard::parse(&text);
+        assert_eq!(board.best().unwrap().distance, 120.5);
+        assert_eq!(board.insert(Entry { distance: 200.0, score: 10.0, time: 5.0, flashes: 0, seed: 1, date: 0 }), Some(0));
+        let mut written = Vec::new();
+        board.write(&mut written);
+        let written = String::from_utf8(written).unwrap();
+        assert_eq!(written.lines().collect::<Vec<_>>(), [HEADER, "200 10 5 0 1 0", "120.5 300 42 3 7 1700000000", "# a note", "80 abc 10 1 7 1700000000"]);
+        assert_eq!(Leaderboard::parse(&written).entries.len(), 2);
+    }
+}
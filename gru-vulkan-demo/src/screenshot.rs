@@ -0,0 +1,148 @@
+use super::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+//reads the pixels once their copy is done, then size, path and metadata of the png
+type Job = (Box<dyn FnOnce() -> Vec<u8> + Send>, (u32, u32), String, Vec<(&'static str, String)>);
+
+//writes captured images as png on a background thread, with a bounded number of captures in flight
+pub struct Encoder
+{
+    device: Device,
+    sender: mpsc::Sender<Job>,
+    thread: std::thread::JoinHandle<()>,
+    pending: Arc<AtomicUsize>,
+    capacity: usize,
+    //wait for a free slot instead of dropping the capture
+    block: bool,
+    //name of the last screenshot and how many were taken under it
+    last: (String, u32)
+}
+
+impl Encoder
+{
+    pub fn new(device: &Device, capacity: usize, block: bool) -> Self
+    {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let thread =
+        {
+            let pending = pending.clone();
+            std::thread::spawn(move ||
+            {
+                for (read, size, path, metadata) in receiver
+                {
+                    write_png(&path, size, read(), &metadata);
+                    pending.fetch_sub(1, Ordering::SeqCst);
+                }
+            })
+        };
+        Self { device: device.clone(), sender, thread, pending, capacity, block, last: (String::new(), 0) }
+    }
+
+    //screenshot_YYYY-MM-DD_HH-MM-SS.png in utc, numbered further if several are taken in the same second,
+    //the ones still queued are counted here, the ones from earlier runs are already on disk
+    pub fn next_path(&mut self, dir: &str) -> String
+    {
+        std::fs::create_dir_all(dir).unwrap_or_else(|err| panic!("{dir}: {err}"));
+        let secs = scores::now();
+        let name = format!("screenshot_{}_{:02}-{:02}-{:02}", scores::date(secs), secs / 3600 % 24, secs / 60 % 60, secs % 60);
+        if self.last.0 == name { self.last.1 += 1; } else { self.last = (name, 1); }
+        loop
+        {
+            let path = match self.last.1
+            {
+                1 => format!("{dir}/{}.png", self.last.0),
+                n => format!("{dir}/{}_{n}.png", self.last.0)
+            };
+            if !std::path::Path::new(&path).exists() { return path; }
+            self.last.1 += 1;
+        }
+    }
+
+    //copies the image and queues it for writing, returns false if the queue is full and the capture was dropped
+    pub fn capture(&self, command_pool: &CommandPool, queue: &Queue, source: CopyImageSource, (width, height): (u32, u32), path: String, metadata: Vec<(&'static str, String)>) -> bool
+    {
+        while self.pending.load(Ordering::SeqCst) >= self.capacity
+        {
+            if !self.block { return false; }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let image_type = ImageType { channel: Swapchain::IMAGE_CHANNEL_TYPE, width, height, layers: ImageLayers::Single };
+        let buffer = self.device.new_image_buffer(image_type);
+        let fence = command_pool.new_command_buffer().copy_from_image(queue, source, &buffer, self.device.new_fence(false));
+        let (command_buffer, fence) = (fence.command_buffer, fence.mark);
+        let read = move ||
+        {
+            fence.wait();
+            drop(command_buffer);
+            let mut image = vec![0; buffer.size()];
+            buffer.read(&mut image);
+            image
+        };
+        self.sender.send((Box::new(read), (width, height), path, metadata)).unwrap();
+        true
+    }
+
+    //waits for everything queued to be written
+    pub fn finish(self)
+    {
+        drop(self.sender);
+        self.thread.join().unwrap();
+    }
+}
+
+//the swapchain's bgra turned into rgba, the metadata goes into text chunks
+pub fn write_png(path: &str, (width, height): (u32, u32), mut image: Vec<u8>, metadata: &[(&'static str, String)])
+{
+    use {image::ImageEncoder, std::io::Write};
+    for pixel in image.chunks_exact_mut(4) { pixel.swap(0, 2); }
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut png).write_image(&image, width, height, image::ExtendedColorType::Rgba8).unwrap_or_else(|err| panic!("{path}: {err}"));
+    //the encoder writes no text chunks, they go right behind the signature and the header chunk
+    let (head, rest) = png.split_at(PNG_HEADER_END);
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path).unwrap_or_else(|err| panic!("{path}: {err}")));
+    file.write_all(head).and_then(|_|
+    {
+        for (key, value) in metadata
+        {
+            let data: Vec<u8> = key.bytes().chain([0]).chain(value.bytes()).collect();
+            file.write_all(&(data.len() as u32).to_be_bytes())?;
+            file.write_all(b"tEXt")?;
+            file.write_all(&data)?;
+            file.write_all(&crc32(b"tEXt".iter().chain(&data)).to_be_bytes())?;
+        }
+        file.write_all(rest)?;
+        file.flush()
+    }).unwrap_or_else(|err| panic!("{path}: {err}"));
+}
+
+//8 bytes signature, then the 13 bytes of the header chunk with length, type and crc around them
+const PNG_HEADER_END: usize = 8 + 4 + 4 + 13 + 4;
+
+//bitwise, there are only a handful of short chunks per image
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32
+{
+    let mut crc = !0u32;
+    for byte in bytes
+    {
+        crc ^= *byte as u32;
+        for _ in 0..8 { crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 }; }
+    }
+    !crc
+}
+
+//what is needed to get back to the captured moment
+pub fn metadata<T: cave::NoiseGradient>(sim: &sim::Simulation<T>, config: &config::Config) -> Vec<(&'static str, String)>
+{
+    vec!
+    [
+        ("Software", "gru-vulkan-demo".to_owned()),
+        ("Seed", sim.seed.to_string()),
+        ("Position", format!("{:.2} {:.2} {:.2}", sim.cam.pos.0, sim.cam.pos.1, sim.cam.pos.2)),
+        ("Biome", biome::BIOMES[biome::Blend::at(sim.cam.pos.2).from].name.to_owned()),
+        ("Score", format!("{:.0}", sim.score())),
+        ("Time", format!("{:.2}", sim.time)),
+        ("Config", config.to_string())
+    ]
+}
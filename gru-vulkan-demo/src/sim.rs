@@ -0,0 +1,259 @@
+use gru_misc::math::*;
+use ahash::AHashMap as HashMap;
+use crate::{mold, cave, cache, camera, pickup, perlin, bindings, tunables, consts};
+
+#[derive(Clone, Copy, Default)]
+pub struct Input
+{
+    pub forward: bool,
+    pub backward: bool,
+    pub left: bool,
+    pub right: bool,
+    pub jump: bool,
+    pub grapple: bool,
+    pub reel: bool,
+    pub toggle_light: bool,
+    pub noclip: Option<bool>,
+    pub look: (f32, f32)
+}
+
+impl Input
+{
+    //movement follows whether the action is held, the rest only reacts to pressing it
+    pub fn action(&mut self, action: bindings::Action, pressed: bool)
+    {
+        match action
+        {
+            bindings::Action::Forward => self.forward = pressed,
+            bindings::Action::Backward => self.backward = pressed,
+            bindings::Action::Left => self.left = pressed,
+            bindings::Action::Right => self.right = pressed,
+            bindings::Action::Jump => if pressed { self.jump = true; },
+            bindings::Action::Grapple => if pressed { self.grapple = true; },
+            bindings::Action::Reel => self.reel = pressed,
+            bindings::Action::ToggleLight => if pressed { self.toggle_light = true; },
+            bindings::Action::NoclipOn => if pressed { self.noclip = Some(true); },
+            bindings::Action::NoclipOff => if pressed { self.noclip = Some(false); },
+            bindings::Action::Screenshot | bindings::Action::ScreenshotLarge | bindings::Action::Fullscreen | bindings::Action::Pause
+                | bindings::Action::Confirm | bindings::Action::NewSeed | bindings::Action::Quit => {}
+        }
+    }
+
+    //one-shot inputs only apply to the step they were pressed in
+    pub fn clear_impulses(&mut self)
+    {
+        self.jump = false;
+        self.grapple = false;
+        self.toggle_light = false;
+        self.noclip = None;
+        self.look = (0.0, 0.0);
+    }
+}
+
+//hands out the rendered frame time in steps, so the simulation does not depend on the frame rate
+pub struct Clock
+{
+    pub tick: f32,
+    accumulator: f32
+}
+
+impl Clock
+{
+    pub fn new(rate: f32) -> Self { Self { tick: 1.0 / rate, accumulator: 0.0 } }
+
+    pub fn advance(&mut self, dt: f32) { self.accumulator += dt.min(consts::MAX_FRAME_TIME); }
+
+    pub fn take(&mut self, dt: f32) -> bool
+    {
+        if self.accumulator < dt { return false; }
+        self.accumulator -= dt;
+        true
+    }
+
+    //how far the rendered frame is between the last two steps
+    pub fn alpha(&self) -> f32 { (self.accumulator / self.tick).min(1.0) }
+}
+
+pub struct Simulation<T: cave::NoiseGradient>
+{
+    pub mold: cave::Cave<T>,
+    pub seed: u32,
+    pub probabilities: [f32; pickup::KINDS.len()],
+    pub cache: Option<cache::Cache>,
+    pub cam: camera::Camera,
+    pub prev_pose: camera::Pose,
+    pub blocks: HashMap<i32, Vec<pickup::Pickup>>,
+    //kind and remaining time of every running effect
+    pub active: Vec<(usize, f32)>,
+    pub time: f32,
+    pub ambient_flash: Vec3,
+    pub flashes_collected: u32,
+    //charge of the flashlight from 0 to 1 and whether the player has it switched on
+    pub battery: f32,
+    pub light_switch: bool,
+    flicker: perlin::Perlin
+}
+
+impl<T: cave::NoiseGradient> Simulation<T>
+{
+    pub fn new(mold: cave::Cave<T>, seed: u32, probabilities: [f32; pickup::KINDS.len()], cache: Option<cache::Cache>) -> Self
+    {
+        let mut cam = camera::Camera::new();
+        cam.pos.0 = mold.x0();
+        cam.pos.1 = mold.y0();
+        let mut sim = Self
+        {
+            mold,
+            seed,
+            probabilities,
+            cache,
+            prev_pose: cam.pose(),
+            cam,
+            blocks: HashMap::new(),
+            active: Vec::new(),
+            time: -tunables::get().wait_time,
+            ambient_flash: Vec3(0.0, 0.0, 0.0),
+            flashes_collected: 0,
+            battery: 1.0,
+            light_switch: true,
+            flicker: perlin::Perlin::new(0)
+        };
+        sim.update_blocks();
+        sim
+    }
+
+    pub fn step(&mut self, dt: f32, input: &Input)
+    {
+        self.prev_pose = self.cam.pose();
+        let cam = &mut self.cam;
+        cam.forward = input.forward;
+        cam.backward = input.backward;
+        cam.left = input.left;
+        cam.right = input.right;
+        cam.reel = input.reel;
+        if let Some(noclip) = input.noclip { cam.does_physics = !noclip; }
+        cam.look(input.look.0, input.look.1);
+        if input.jump { cam.jump(); }
+        if input.grapple { cam.grapple(&self.mold); }
+        if input.toggle_light { self.light_switch = !self.light_switch; }
+
+        let modifiers = self.modifiers();
+        self.cam.jump_scale = modifiers.jump;
+        self.cam.gravity_scale = modifiers.gravity;
+        self.time += dt * modifiers.bias;
+        for (_, remaining) in &mut self.active { *remaining -= dt; }
+        self.active.retain(|(_, remaining)| *remaining > 0.0);
+        self.ambient_flash = self.ambient_flash * tunables::get().flash_ambient_decay.powf(dt);
+        if self.light_switch { self.battery = (self.battery - tunables::get().battery_drain * dt).max(0.0); }
+        self.cam.logic(dt, &self.mold);
+        //pickup, anywhere along the body
+        let cam_norm = self.cam_norm();
+        let capsule = self.cam.capsule();
+        for block_z in &[cam_norm.floor() as i32, cam_norm.ceil() as i32]
+        {
+            if let Some(pickups) = self.blocks.get_mut(block_z)
+                && let Some(i) = pickups.iter().position(|pickup| capsule.overlaps(&mold::Translate { mold: mold::Sphere(tunables::get().pickup_range), offset: pickup.pos }, self.cam.pos))
+            {
+                let pickup = pickups.remove(i);
+                self.collect(pickup);
+            }
+        }
+        self.update_blocks();
+    }
+
+    fn collect(&mut self, pickup: pickup::Pickup)
+    {
+        let kind = &pickup::KINDS[pickup.kind];
+        match kind.effect
+        {
+            pickup::Effect::Time =>
+            {
+                self.time -= tunables::get().flash_power;
+                self.ambient_flash = self.ambient_flash + pickup.color * tunables::get().flash_ambient_power;
+                self.flashes_collected += 1;
+                self.battery = (self.battery + tunables::get().flash_battery).min(1.0);
+            },
+            pickup::Effect::Battery(charge) => self.battery = (self.battery + charge).min(1.0),
+            //collecting a running effect again starts it over
+            _ => match self.active.iter_mut().find(|(active, _)| *active == pickup.kind)
+            {
+                Some((_, remaining)) => *remaining = kind.duration,
+                None => self.active.push((pickup.kind, kind.duration))
+            }
+        }
+    }
+
+    //brightness of the flashlight, dimming and flickering more the emptier the battery gets
+    pub fn light(&self) -> f32
+    {
+        if !self.light_switch || self.battery == 0.0 { return 0.0; }
+        let level = (self.battery / tunables::get().battery_low).min(1.0);
+        let flicker = self.flicker.get2([self.time as f64 * consts::BATTERY_FLICKER_FREQUENCY, 0.0]).0 as f32;
+        if flicker > level { 0.0 } else { consts::BATTERY_MIN_BRIGHTNESS + (1.0 - consts::BATTERY_MIN_BRIGHTNESS) * level }
+    }
+
+    pub fn modifiers(&self) -> pickup::Modifiers { pickup::Modifiers::new(&self.active) }
+
+    fn update_blocks(&mut self)
+    {
+        let cam_norm = self.cam_norm();
+        self.blocks.retain(|z, _| ((cam_norm - *z as f32).floor() as i32) < tunables::get().block_despawn_back_distance);
+        for z in self.block_range()
+        {
+            if !self.blocks.contains_key(&z)
+            {
+                let pickups = self.cache.as_ref().and_then(|cache| cache.load_pickups(z)).unwrap_or_else(||
+                {
+                    let pickups = cave::place_pickups(&self.mold, self.seed, z, &self.probabilities);
+                    if let Some(Err(err)) = self.cache.as_ref().map(|cache| cache.store_pickups(z, &pickups)) { eprintln!("pickups of block {z} not cached: {err}"); }
+                    pickups
+                });
+                self.blocks.insert(z, pickups);
+            }
+        }
+    }
+
+    pub fn pose(&self, alpha: f32) -> camera::Pose { self.prev_pose.lerp(self.cam.pose(), alpha) }
+
+    pub fn cam_norm(&self) -> f32 { self.cam.pos.2 / tunables::get().block_length }
+
+    pub fn block_range(&self) -> std::ops::RangeInclusive<i32>
+    {
+        let center = self.cam_norm().round() as i32;
+        center..=(center + tunables::get().block_spawn_front_distance)
+    }
+
+    pub fn pickups(&self) -> impl Iterator<Item = &pickup::Pickup> { self.blocks.values().flatten() }
+
+    pub fn z_bias(&self) -> f32 { tunables::get().z_bias_offset + tunables::get().c * self.time.max(0.0) }
+    pub fn score(&self) -> f32 { tunables::get().max_bias - (self.z_bias() - self.cam.pos.2) }
+    pub fn over(&self) -> bool { self.score() <= 0.0 }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::config;
+
+    //replays depend on the same seed and inputs ending up in the same place
+    #[test]
+    fn deterministic()
+    {
+        tunables::init_default();
+        let config = config::Config::new();
+        let run = ||
+        {
+            let mut sim = Simulation::new(config.mold(), config.seed, pickup::probabilities(), None);
+            for i in 0..600
+            {
+                let input = Input { forward: true, jump: i % 60 == 0, ..Input::default() };
+                sim.step(1.0 / consts::TICK_RATE, &input);
+            }
+            (sim.cam.pos.0, sim.cam.pos.1, sim.cam.pos.2, sim.score())
+        };
+        let first = run();
+        assert_eq!(first, run());
+        assert!(first.2 > 1.0, "did not move forward: {first:?}");
+    }
+}
@@ -0,0 +1,305 @@
+use gru_misc::math::*;
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+use std::{cmp::Reverse, collections::BinaryHeap};
+use crate::{cave::BlockMesh, mold, consts, tunables};
+
+//vertex and index counts of meshes, straight out of marching cubes and after clean up
+#[derive(Clone, Copy, Default)]
+pub struct Counts
+{
+    pub vertices: [u64; 2],
+    pub indices: [u64; 2]
+}
+
+impl std::ops::AddAssign for Counts
+{
+    fn add_assign(&mut self, other: Self)
+    {
+        for i in 0..2
+        {
+            self.vertices[i] += other.vertices[i];
+            self.indices[i] += other.indices[i];
+        }
+    }
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 { a.0 * b.0 + a.1 * b.1 + a.2 * b.2 }
+fn cross(a: Vec3, b: Vec3) -> Vec3 { Vec3(a.1 * b.2 - a.2 * b.1, a.2 * b.0 - a.0 * b.2, a.0 * b.1 - a.1 * b.0) }
+
+//welds, drops degenerate triangles and decimates within mesh_simplify_error, 0 leaves out the decimation
+pub fn process(mesh: &mut BlockMesh) -> Counts
+{
+    let before = (mesh.vertices.len() as u64, mesh.indices.len() as u64);
+    weld(mesh, consts::MESH_WELD_EPSILON);
+    remove_degenerate(mesh);
+    let max_error = tunables::get().mesh_simplify_error;
+    if max_error > 0.0 { decimate(mesh, max_error); }
+    Counts { vertices: [before.0, mesh.vertices.len() as u64], indices: [before.1, mesh.indices.len() as u64] }
+}
+
+//marching cubes emits the vertices on shared cell edges once per cell, the first one of every position is kept,
+//positions within epsilon of each other can lie in neighbouring grid cells, so those are searched as well
+pub fn weld(mesh: &mut BlockMesh, epsilon: f32)
+{
+    let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+    let mut vertices: Vec<mold::Vertex> = Vec::new();
+    let mut remap = Vec::with_capacity(mesh.vertices.len());
+    for vertex in mesh.vertices.drain(..)
+    {
+        let cell = [vertex.position.0, vertex.position.1, vertex.position.2].map(|x| (x / epsilon).floor() as i64);
+        let neighbours = (0..27).map(|i| [cell[0] + i % 3 - 1, cell[1] + i / 3 % 3 - 1, cell[2] + i / 9 - 1]);
+        let found = neighbours.filter_map(|key| grid.get(&key)).flatten().find(|&&i| (vertices[i as usize].position - vertex.position).norm() <= epsilon).copied();
+        remap.push(found.unwrap_or_else(||
+        {
+            grid.entry(cell).or_default().push(vertices.len() as u32);
+            vertices.push(vertex);
+            vertices.len() as u32 - 1
+        }));
+    }
+    for index in &mut mesh.indices { *index = remap[*index as usize]; }
+    mesh.vertices = vertices;
+}
+
+//triangles that collapsed onto a point or a line, the vertices only they used go as well
+pub fn remove_degenerate(mesh: &mut BlockMesh)
+{
+    let triangles: Vec<[u32; 3]> = mesh.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).filter(|&[a, b, c]|
+    {
+        let [a, b, c] = [a, b, c].map(|i| mesh.vertices[i as usize].position);
+        cross(b - a, c - a).norm() > consts::MESH_DEGENERATE_AREA
+    }).collect();
+    compact(mesh, &triangles);
+}
+
+//rebuilds the mesh from the given triangles, dropping unreferenced vertices
+fn compact(mesh: &mut BlockMesh, triangles: &[[u32; 3]])
+{
+    let mut remap = vec![u32::MAX; mesh.vertices.len()];
+    let mut vertices = Vec::new();
+    let mut old: Vec<_> = mesh.vertices.drain(..).map(Some).collect();
+    mesh.indices = triangles.iter().flatten().map(|&i|
+    {
+        if remap[i as usize] == u32::MAX
+        {
+            remap[i as usize] = vertices.len() as u32;
+            vertices.push(old[i as usize].take().unwrap());
+        }
+        remap[i as usize]
+    }).collect();
+    mesh.vertices = vertices;
+}
+
+//plane equations summed up as symmetric 4x4 matrices, xᵀQx is the sum of squared distances to the planes
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric
+{
+    fn plane(normal: Vec3, point: Vec3) -> Self
+    {
+        let [a, b, c] = [normal.0, normal.1, normal.2].map(|x| x as f64);
+        let d = -(a * point.0 as f64 + b * point.1 as f64 + c * point.2 as f64);
+        Self([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d])
+    }
+
+    fn add(self, other: Self) -> Self { Self(std::array::from_fn(|i| self.0[i] + other.0[i])) }
+
+    fn error(&self, p: Vec3) -> f64
+    {
+        let [a2, ab, ac, ad, b2, bc, bd, c2, cd, d2] = self.0;
+        let (x, y, z) = (p.0 as f64, p.1 as f64, p.2 as f64);
+        a2 * x * x + 2.0 * ab * x * y + 2.0 * ac * x * z + 2.0 * ad * x + b2 * y * y + 2.0 * bc * y * z + 2.0 * bd * y + c2 * z * z + 2.0 * cd * z + d2
+    }
+}
+
+//quadric error edge collapses (garland and heckbert), every collapse moves a vertex onto its neighbour,
+//so the remaining vertices keep the exact normals and colors of the mold,
+//vertices on the border of the mesh stay put, so the block faces and their skirts still line up
+pub fn decimate(mesh: &mut BlockMesh, max_error: f32)
+{
+    let mut triangles: Vec<[u32; 3]> = mesh.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+    let mut alive = vec![true; triangles.len()];
+    let mut around = vec![Vec::new(); mesh.vertices.len()];
+    let mut quadrics = vec![Quadric::default(); mesh.vertices.len()];
+    let mut edges = HashMap::new();
+    for (t, triangle) in triangles.iter().enumerate()
+    {
+        let [a, b, c] = triangle.map(|i| mesh.vertices[i as usize].position);
+        let quadric = Quadric::plane(cross(b - a, c - a).unit(), a);
+        for k in 0..3
+        {
+            let (i, j) = (triangle[k], triangle[(k + 1) % 3]);
+            around[i as usize].push(t);
+            quadrics[i as usize] = quadrics[i as usize].add(quadric);
+            *edges.entry((i.min(j), i.max(j))).or_insert(0) += 1;
+        }
+    }
+    let mut locked = vec![false; mesh.vertices.len()];
+    for (&(i, j), &count) in &edges { if count != 2 { locked[i as usize] = true; locked[j as usize] = true; } }
+
+    //collapses by error, entries whose vertices changed since are skipped when they come up
+    let mut version = vec![0u32; mesh.vertices.len()];
+    let mut heap = BinaryHeap::new();
+    let max_error = (max_error as f64).powi(2);
+    let push = |heap: &mut BinaryHeap<_>, quadrics: &[Quadric], version: &[u32], from: u32, to: u32|
+    {
+        if locked[from as usize] { return; }
+        let error = quadrics[from as usize].add(quadrics[to as usize]).error(mesh.vertices[to as usize].position).max(0.0);
+        if error <= max_error { heap.push(Reverse((error.to_bits(), from, to, version[from as usize], version[to as usize]))); }
+    };
+    for &(i, j) in edges.keys()
+    {
+        push(&mut heap, &quadrics, &version, i, j);
+        push(&mut heap, &quadrics, &version, j, i);
+    }
+
+    while let Some(Reverse((_, from, to, from_version, to_version))) = heap.pop()
+    {
+        let (u, v) = (from as usize, to as usize);
+        if version[u] != from_version || version[v] != to_version { continue; }
+        let (shared, moved): (Vec<usize>, Vec<usize>) = around[u].iter().copied().filter(|&t| alive[t]).partition(|&t| triangles[t].contains(&to));
+        if shared.is_empty() { continue; }
+        //the link condition, anything else pinches the surface
+        let (u_neighbours, v_neighbours) = (neighbours(&around[u], &alive, &triangles), neighbours(&around[v], &alive, &triangles));
+        if u_neighbours.intersection(&v_neighbours).filter(|&&w| w != from && w != to).count() != shared.len() { continue; }
+        //no triangle may flip over, turn too far or become degenerate
+        let target = mesh.vertices[v].position;
+        let folds = moved.iter().any(|&t|
+        {
+            let [a, b, c] = triangles[t].map(|i| mesh.vertices[i as usize].position);
+            let [a2, b2, c2] = triangles[t].map(|i| if i == from { target } else { mesh.vertices[i as usize].position });
+            let (old, new) = (cross(b - a, c - a), cross(b2 - a2, c2 - a2));
+            new.norm() <= consts::MESH_DEGENERATE_AREA || dot(old, new) <= old.norm() * new.norm() * consts::MESH_SIMPLIFY_MIN_COS
+        });
+        if folds { continue; }
+
+        for &t in &shared { alive[t] = false; }
+        for &t in &moved
+        {
+            for i in &mut triangles[t] { if *i == from { *i = to; } }
+            around[v].push(t);
+        }
+        //every entry with u or v in it is stale now, the edges around v are queued again
+        quadrics[v] = quadrics[v].add(quadrics[u]);
+        version[u] += 1;
+        version[v] += 1;
+        for &w in neighbours(&around[v], &alive, &triangles).iter().filter(|&&w| w != to)
+        {
+            push(&mut heap, &quadrics, &version, to, w);
+            push(&mut heap, &quadrics, &version, w, to);
+        }
+    }
+
+    let triangles: Vec<_> = triangles.into_iter().zip(alive).filter_map(|(triangle, alive)| alive.then_some(triangle)).collect();
+    compact(mesh, &triangles);
+}
+
+//the vertices of the live triangles, including the vertex itself
+fn neighbours(around: &[usize], alive: &[bool], triangles: &[[u32; 3]]) -> HashSet<u32>
+{
+    around.iter().filter(|&&t| alive[t]).flat_map(|&t| triangles[t]).collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn vertex(position: Vec3) -> mold::Vertex
+    {
+        mold::Vertex { position, normal: Vec3(0.0, 0.0, 1.0), color: Vec3(1.0, 1.0, 1.0) }
+    }
+
+    //a square height field, every triangle with its own vertices like marching cubes emits them
+    fn height_field(n: usize, height: impl Fn(f32, f32) -> f32) -> BlockMesh
+    {
+        let mut mesh = BlockMesh { vertices: Vec::new(), indices: Vec::new() };
+        for i in 0..n
+        {
+            for j in 0..n
+            {
+                for (a, b) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+                {
+                    let (x, y) = (i as f32 + a, j as f32 + b);
+                    mesh.indices.push(mesh.vertices.len() as u32);
+                    mesh.vertices.push(vertex(Vec3(x, y, height(x, y))));
+                }
+            }
+        }
+        mesh
+    }
+
+    #[test]
+    fn weld_duplicates()
+    {
+        //the second pair straddles a grid cell boundary
+        let positions = [Vec3(1.0, 2.0, 3.0), Vec3(0.0, 0.0, 0.0), Vec3(1.0, 2.0, 3.0), Vec3(-1e-7, 0.0, 0.0), Vec3(1.0, 0.0, 0.0)];
+        let mut mesh = BlockMesh { vertices: positions.into_iter().map(vertex).collect(), indices: vec![0, 1, 4, 2, 3, 4] };
+        weld(&mut mesh, 1e-5);
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 1, 2]);
+
+        let mut mesh = height_field(4, |_, _| 0.0);
+        weld(&mut mesh, consts::MESH_WELD_EPSILON);
+        assert_eq!(mesh.vertices.len(), 25);
+        assert_eq!(mesh.indices.len(), 4 * 4 * 6);
+    }
+
+    #[test]
+    fn degenerate_removed()
+    {
+        let positions = [Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0), Vec3(5.0, 5.0, 5.0)];
+        let mut mesh = BlockMesh { vertices: positions.into_iter().map(vertex).collect(), indices: vec![0, 1, 2, 3, 3, 0, 1, 1, 2] };
+        remove_degenerate(&mut mesh);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        //the vertex only the degenerate triangles used goes with them
+        assert_eq!(mesh.vertices.len(), 3);
+    }
+
+    #[test]
+    fn decimate_within_bound()
+    {
+        let n = 20;
+        let max_error = 0.05;
+        let hill = |x: f32, y: f32| 2.0 * (1.0 - ((x - 10.0).powi(2) + (y - 10.0).powi(2)) / 9.0).max(0.0).powi(2);
+        let mut mesh = height_field(n, hill);
+        weld(&mut mesh, consts::MESH_WELD_EPSILON);
+        decimate(&mut mesh, max_error);
+        assert!(mesh.vertices.len() < 250, "{} vertices left", mesh.vertices.len());
+
+        //the border is locked, so the outline stays closed and keeps all of its vertices
+        let border = mesh.vertices.iter().filter(|v| [v.position.0, v.position.1].iter().any(|&x| x == 0.0 || x == n as f32)).count();
+        assert_eq!(border, 4 * n);
+        let mut edges = HashMap::new();
+        for triangle in mesh.indices.chunks(3)
+        {
+            for k in 0..3
+            {
+                let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+                assert_ne!(a, b);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        assert!(edges.values().all(|&count| count == 1 || count == 2));
+        assert_eq!(edges.values().filter(|&&count| count == 1).count(), 4 * n);
+
+        //every original grid point stays within the bound of the triangle above or below it, none flipped
+        for i in 0..=n
+        {
+            for j in 0..=n
+            {
+                let (x, y) = (i as f32, j as f32);
+                let height = mesh.indices.chunks(3).find_map(|triangle|
+                {
+                    let [a, b, c] = [0, 1, 2].map(|k| mesh.vertices[triangle[k] as usize].position);
+                    let area = (b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1);
+                    assert!(area > 0.0);
+                    let u = ((b.0 - x) * (c.1 - y) - (c.0 - x) * (b.1 - y)) / area;
+                    let v = ((c.0 - x) * (a.1 - y) - (a.0 - x) * (c.1 - y)) / area;
+                    (u >= -1e-5 && v >= -1e-5 && u + v <= 1.0 + 1e-5).then_some(u * a.2 + v * b.2 + (1.0 - u - v) * c.2)
+                }).unwrap();
+                assert!((height - hill(x, y)).abs() <= max_error, "{x} {y}: {height} {}", hill(x, y));
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::OnceLock;
+use crate::consts;
+
+macro_rules! tunables
+{
+    ($($name: ident: $ty: ty = $default: expr),* $(,)?) =>
+    {
+        //gameplay and generation parameters that can be changed in the config file, the consts are only their defaults
+        #[derive(Clone, PartialEq)]
+        pub struct Tunables
+        {
+            $(pub $name: $ty),*
+        }
+
+        impl Tunables
+        {
+            pub fn new() -> Self { Self { $($name: $default),* } }
+
+            pub fn set(&mut self, key: &str, value: &str) -> Result<(), String>
+            {
+                match key
+                {
+                    $(stringify!($name) => self.$name = value.parse().map_err(|_| format!("invalid value \"{value}\""))?,)*
+                    _ => return Err(format!("unknown key \"{key}\""))
+                }
+                Ok(())
+            }
+
+            //every value as key and text that parses back to the exact same value
+            pub fn entries(&self) -> Vec<(&'static str, String)> { vec![$((stringify!($name), self.$name.to_string())),*] }
+        }
+    };
+}
+
+tunables!
+{
+    cave_radius: f32 = consts::CAVE_RADIUS,
+    block_length: f32 = consts::BLOCK_LENGTH,
+    block_spawn_front_distance: i32 = consts::BLOCK_SPAWN_FRONT_DISTANCE,
+    block_despawn_back_distance: i32 = consts::BLOCK_DESPAWN_BACK_DISTANCE,
+    cave_resolution: u32 = consts::CAVE_RESOLUTION,
+    cave_gen_bias: f32 = consts::CAVE_GEN_BIAS,
+    cave_gen_border_power: i32 = consts::CAVE_GEN_BORDER_POWER,
+    cave_gen_border_strength: f32 = consts::CAVE_GEN_BORDER_STRENGTH,
+    cave_gen_spawn_strength: f32 = consts::CAVE_GEN_SPAWN_STRENGTH,
+    cave_gen_spawn_decay_rate: f32 = consts::CAVE_GEN_SPAWN_DECAY_RATE,
+    cave_chamber_spacing: f32 = consts::CAVE_CHAMBER_SPACING,
+    cave_tunnel_spacing: f32 = consts::CAVE_TUNNEL_SPACING,
+    cave_pillar_spacing: f32 = consts::CAVE_PILLAR_SPACING,
+    cave_stalactite_spacing: f32 = consts::CAVE_STALACTITE_SPACING,
+    mesh_simplify_error: f32 = consts::MESH_SIMPLIFY_ERROR,
+    mouse_sensitivity: f32 = consts::MOUSE_SENSITIVITY,
+    c: f32 = consts::C,
+    wait_time: f32 = consts::WAIT_TIME,
+    z_bias_offset: f32 = consts::Z_BIAS_OFFSET,
+    max_bias: f32 = consts::MAX_BIAS,
+    battery_drain: f32 = consts::BATTERY_DRAIN,
+    battery_low: f32 = consts::BATTERY_LOW,
+    grav: f32 = consts::GRAV,
+    surface_drag: f32 = consts::SURFACE_DRAG,
+    air_drag: f32 = consts::AIR_DRAG,
+    acceleration: f32 = consts::ACCELERATION,
+    min_acceleration: f32 = consts::MIN_ACCELERATION,
+    jump: f32 = consts::JUMP,
+    jump_cooldown: f32 = consts::JUMP_COOLDOWN,
+    control_time: f32 = consts::CONTROL_TIME,
+    hook_range: f32 = consts::HOOK_RANGE,
+    hook_pull: f32 = consts::HOOK_PULL,
+    flash_power: f32 = consts::FLASH_POWER,
+    flash_ambient_power: f32 = consts::FLASH_AMBIENT_POWER,
+    flash_ambient_decay: f32 = consts::FLASH_AMBIENT_DECAY,
+    flash_battery: f32 = consts::FLASH_BATTERY,
+    pickup_range: f32 = consts::PICKUP_RANGE
+}
+
+impl Tunables
+{
+    //every further detail level halves the resolution
+    pub fn lod_resolution(&self, lod: usize) -> u32 { self.cave_resolution >> lod }
+
+    //has to reach past the far end of the background box
+    pub fn cam_far(&self) -> f32 { self.block_length * self.block_spawn_front_distance as f32 * 1.5 }
+
+    //the box bg.vert draws around the loaded part of the cave
+    pub fn bg_radius(&self) -> f32 { 2.0 * self.cave_radius }
+    pub fn bg_back_distance(&self) -> f32 { self.block_length * (self.block_despawn_back_distance + 1) as f32 }
+    pub fn bg_front_distance(&self) -> f32 { self.block_length * (self.block_spawn_front_distance + 1) as f32 }
+
+    pub fn check(&self) -> Result<(), String>
+    {
+        if self.cave_radius <= 0.0 || self.block_length <= 0.0 { return Err("cave_radius and block_length must be positive".to_owned()); }
+        if self.block_spawn_front_distance < 0 || self.block_despawn_back_distance < 0 { return Err("block distances must not be negative".to_owned()); }
+        if [self.cave_chamber_spacing, self.cave_tunnel_spacing, self.cave_pillar_spacing, self.cave_stalactite_spacing].iter().any(|spacing| *spacing < 0.0) { return Err("cave feature spacings must not be negative".to_owned()); }
+        if self.hook_range < 0.0 || self.hook_pull < 0.0 { return Err("hook_range and hook_pull must not be negative".to_owned()); }
+        if self.mesh_simplify_error < 0.0 { return Err("mesh_simplify_error must not be negative".to_owned()); }
+        if self.lod_resolution(consts::CAVE_LOD_DISTANCES.len()) < 2 { return Err(format!("cave_resolution {} is too low for every detail level", self.cave_resolution)); }
+        Ok(())
+    }
+}
+
+static TUNABLES: OnceLock<Tunables> = OnceLock::new();
+
+//fixed for the whole run, has to happen before anything reads them
+pub fn init(tunables: Tunables)
+{
+    if TUNABLES.set(tunables).is_err() { panic!("tunables initialized twice"); }
+}
+
+pub fn get() -> &'static Tunables { TUNABLES.get().expect("tunables not initialized") }
+
+//tests all run on the defaults, whichever comes first sets them
+#[cfg(test)]
+pub fn init_default() { TUNABLES.get_or_init(Tunables::new); }